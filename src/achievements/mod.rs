@@ -0,0 +1,386 @@
+use bevy::prelude::*;
+use super::{AppState, Controller, Difficulty, GameMode, Handicap, MatchRules, Players, ScoreBoard, Side};
+use super::events::{MatchWon, PaddleHit};
+use super::net::{ClientSession, HostSession};
+use super::storage;
+use super::ui::{self, OVERLAY_COLOR, TEXT_COLOR, text_bundle};
+
+pub struct AchievementsPlugin;
+
+struct Achievement {
+    // Used in the save file
+    id: &'static str,
+    name: &'static str,
+    description: &'static str,
+}
+
+const ACHIEVEMENTS: [Achievement; 7] = [
+    Achievement { id: "first-win", name: "First win", description: "Win a match" },
+    Achievement { id: "clean-sheet", name: "Clean sheet", description: "Win a match without conceding a point" },
+    Achievement { id: "rally-20", name: "Keep it going", description: "Play a rally of 20 hits" },
+    Achievement { id: "rally-50", name: "Marathon", description: "Play a rally of 50 hits" },
+    Achievement { id: "rocket", name: "Rocket", description: "Send the ball back at a speed of 30" },
+    Achievement { id: "underdog", name: "Against the odds", description: "Win with a handicap" },
+    Achievement { id: "machine-breaker", name: "Machine breaker", description: "Beat a hard computer player" },
+];
+
+const RALLY_ACHIEVEMENTS: [(i32, &str); 2] = [(20, "rally-20"), (50, "rally-50")];
+const ROCKET_SPEED: f32 = 30.0;
+
+// Achievements unlocked by each profile, as (profile name, achievement id)
+struct Unlocked(Vec<(String, String)>);
+
+impl Unlocked {
+    fn has(&self, name: &str, id: &str) -> bool {
+        self.0.iter().any(|(profile, achievement)| profile == name && achievement == id)
+    }
+}
+
+// Shown at the top of the screen for a while after an unlock
+#[derive(Component)]
+struct Toast {
+    timer: Timer,
+}
+
+struct AchievementsScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for AchievementsScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+const ACHIEVEMENTS_FILE: &str = "achievements";
+const TOAST_SECONDS: f32 = 3.0;
+
+const LOCKED_COLOR: Color = Color::rgb(0.55, 0.55, 0.55);
+const UNLOCKED_COLOR: Color = Color::rgb(0.92, 0.39, 0.20);
+
+impl Plugin for AchievementsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(load_unlocked())
+        // Events are checked in every state, the winning point already moves on to GameOver
+        .add_system(check_achievements)
+        .add_system(update_toasts)
+        .add_system_set(
+            SystemSet::on_enter(AppState::Achievements)
+                .with_system(setup_achievements_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Achievements)
+                .with_system(ui::back_controls)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Achievements)
+                .with_system(ui::cleanup::<AchievementsScreen>)
+        );
+    }
+}
+
+fn load_unlocked() -> Unlocked {
+    match storage::load(ACHIEVEMENTS_FILE) {
+        Some(contents) => parse_unlocked(&contents),
+        None => Unlocked(Vec::new())
+    }
+}
+
+// One unlock per line, the profile name goes last since it can have spaces in it
+fn parse_unlocked(contents: &str) -> Unlocked {
+    let mut unlocked = Unlocked(Vec::new());
+
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match line.split_once(' ') {
+            Some((id, name)) if ACHIEVEMENTS.iter().any(|achievement| achievement.id == id) && !name.is_empty() => {
+                unlocked.0.push((name.to_string(), id.to_string()));
+            },
+            _ => warn!("Ignoring '{}' in {}", line, ACHIEVEMENTS_FILE),
+        }
+    }
+
+    unlocked
+}
+
+fn save_unlocked(unlocked: &Unlocked) {
+    storage::save(ACHIEVEMENTS_FILE, &unlocked_contents(unlocked));
+}
+
+fn unlocked_contents(unlocked: &Unlocked) -> String {
+    unlocked.0.iter()
+        .map(|(name, id)| format!("{} {}\n", id, name))
+        .collect()
+}
+
+// Achievements belong to profiles, so only named human players earn them
+fn profile_name(players: &Players, mode: GameMode, side: Side) -> Option<&str> {
+    let settings = &players.0[side.index()];
+
+    if mode.sides().contains(&side) && !settings.name.is_empty() && players.is_human(side) {
+        Some(&settings.name)
+    } else {
+        None
+    }
+}
+
+// Worse off than an opponent: a shorter or slower paddle, or giving away a head start
+fn has_handicap(rules: &MatchRules, side: Side) -> bool {
+    let own = &rules.handicaps[side.index()];
+
+    own.paddle_height < Handicap::default().paddle_height
+        || own.paddle_speed < Handicap::default().paddle_speed
+        || rules.mode.sides().iter().any(|other| rules.handicaps[other.index()].starting_points > own.starting_points)
+}
+
+// Practice runs and network matches don't count, same as the profile stats
+fn check_achievements(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    scoreboard: Res<ScoreBoard>,
+    host_session: Option<Res<HostSession>>,
+    client_session: Option<Res<ClientSession>>,
+    mut unlocked: ResMut<Unlocked>,
+    mut paddle_hits: EventReader<PaddleHit>,
+    mut match_won: EventReader<MatchWon>,
+    toast_query: Query<&Toast>
+) {
+    // Read either way, so the events don't come up again once they do count
+    let hits: Vec<&PaddleHit> = paddle_hits.iter().collect();
+    let wins: Vec<&MatchWon> = match_won.iter().collect();
+
+    if rules.mode == GameMode::Practice || host_session.is_some() || client_session.is_some() {
+        return;
+    }
+
+    let mode = rules.mode;
+    let mut earned: Vec<(&str, &str)> = Vec::new();
+
+    for hit in hits {
+        // A rally belongs to everyone playing it
+        for (length, id) in RALLY_ACHIEVEMENTS {
+            if scoreboard.longest_rally >= length {
+                earned.extend(mode.sides().iter().filter_map(|side| profile_name(&players, mode, *side)).map(|name| (name, id)));
+            }
+        }
+
+        if hit.speed >= ROCKET_SPEED {
+            earned.extend(profile_name(&players, mode, hit.side).map(|name| (name, "rocket")));
+        }
+    }
+
+    for won in wins {
+        let name = match profile_name(&players, mode, won.winner) {
+            Some(name) => name,
+            None => continue
+        };
+
+        earned.push((name, "first-win"));
+
+        let conceded = match won.winner {
+            Side::Left if mode.sides().len() == 2 => Some(scoreboard.player2),
+            Side::Right if mode.sides().len() == 2 => Some(scoreboard.player1),
+            _ => None
+        };
+
+        if conceded == Some(0) {
+            earned.push((name, "clean-sheet"));
+        }
+
+        if has_handicap(&rules, won.winner) {
+            earned.push((name, "underdog"));
+        }
+
+        let beat_hard = mode.sides().iter()
+            .any(|side| players.0[side.index()].controller == Controller::Computer(Difficulty::Hard));
+
+        if beat_hard {
+            earned.push((name, "machine-breaker"));
+        }
+    }
+
+    if earned.is_empty() {
+        return;
+    }
+
+    let mut toasts = toast_query.iter().count();
+    let mut changed = false;
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    for (name, id) in earned {
+        if unlocked.has(name, id) {
+            continue;
+        }
+
+        unlocked.0.push((name.to_string(), id.to_string()));
+        changed = true;
+
+        if let Some(achievement) = ACHIEVEMENTS.iter().find(|achievement| achievement.id == id) {
+            spawn_toast(&mut commands, &format!("{} unlocked {}!", name, achievement.name), toasts, &font);
+            toasts += 1;
+        }
+    }
+
+    if changed {
+        save_unlocked(&unlocked);
+    }
+}
+
+fn spawn_toast(commands: &mut Commands, text: &str, index: usize, font: &Handle<Font>) {
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(100.0 + index as f32 * 50.0),
+                left: Val::Percent(30.0),
+                ..Default::default()
+            },
+            size: Size::new(Val::Percent(40.0), Val::Px(40.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: OVERLAY_COLOR.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle(text, 20.0, UNLOCKED_COLOR, font));
+    })
+    .insert(Toast { timer: Timer::from_seconds(TOAST_SECONDS, false) });
+}
+
+fn update_toasts(mut commands: Commands, time: Res<Time>, mut toast_query: Query<(Entity, &mut Toast)>) {
+    for (entity, mut toast) in toast_query.iter_mut() {
+        if toast.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn setup_achievements_screen(mut commands: Commands, asset_server: Res<AssetServer>, unlocked: Res<Unlocked>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    let ui_root = commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("Achievements", 50.0, TEXT_COLOR, &font));
+
+        for achievement in ACHIEVEMENTS.iter() {
+            let names: Vec<&str> = unlocked.0.iter()
+                .filter(|(_, id)| id == achievement.id)
+                .map(|(name, _)| name.as_str())
+                .collect();
+
+            let (color, unlocked_by) = if names.is_empty() {
+                (LOCKED_COLOR, "Locked".to_string())
+            } else {
+                (UNLOCKED_COLOR, names.join(", "))
+            };
+
+            parent.spawn_bundle(NodeBundle {
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                for (cell, width, cell_color) in [
+                    (achievement.name.to_string(), 200.0, color),
+                    (achievement.description.to_string(), 450.0, TEXT_COLOR),
+                    (unlocked_by, 350.0, color),
+                ] {
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(width), Val::Px(30.0)),
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn_bundle(text_bundle(&cell, 20.0, cell_color, &font));
+                    });
+                }
+            });
+        }
+
+        ui::spawn_back_button(parent, &font);
+    }).id();
+
+    commands.insert_resource(AchievementsScreen { ui_root });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlock(name: &str, id: &str) -> (String, String) {
+        (name.to_string(), id.to_string())
+    }
+
+    #[test]
+    fn unlocks_read_back_what_was_written() {
+        let unlocked = Unlocked(vec![
+            unlock("Ann", "first-win"),
+            unlock("Ann Lee", "rally-50"),
+            unlock("Bob", "machine-breaker"),
+        ]);
+
+        let loaded = parse_unlocked(&unlocked_contents(&unlocked));
+
+        assert_eq!(loaded.0, unlocked.0);
+        assert!(loaded.has("Ann Lee", "rally-50"));
+        assert!(!loaded.has("Ann", "rally-50"));
+    }
+
+    #[test]
+    fn unknown_unlocks_are_skipped() {
+        let loaded = parse_unlocked("first-win Ann\n\nflawless Ann\nrocket\nrocket \nrally-20 Bob\n");
+
+        assert_eq!(loaded.0, [unlock("Ann", "first-win"), unlock("Bob", "rally-20")]);
+    }
+
+    #[test]
+    fn even_match_has_no_handicap() {
+        let rules = MatchRules::default();
+
+        assert!(!has_handicap(&rules, Side::Left));
+        assert!(!has_handicap(&rules, Side::Right));
+    }
+
+    #[test]
+    fn shorter_or_slower_paddle_is_a_handicap() {
+        let mut rules = MatchRules::default();
+        rules.handicaps[Side::Left.index()].paddle_height = 0.75;
+        rules.handicaps[Side::Right.index()].paddle_speed = 0.5;
+
+        assert!(has_handicap(&rules, Side::Left));
+        assert!(has_handicap(&rules, Side::Right));
+
+        // A bigger or faster paddle is a help, not a handicap
+        rules.handicaps[Side::Left.index()].paddle_height = 1.5;
+        rules.handicaps[Side::Right.index()].paddle_speed = 1.25;
+
+        assert!(!has_handicap(&rules, Side::Left));
+        assert!(!has_handicap(&rules, Side::Right));
+    }
+
+    #[test]
+    fn head_start_is_a_handicap_for_the_opponent() {
+        let mut rules = MatchRules::default();
+        rules.handicaps[Side::Right.index()].starting_points = 2;
+
+        assert!(has_handicap(&rules, Side::Left));
+        assert!(!has_handicap(&rules, Side::Right));
+    }
+
+    #[test]
+    fn sides_out_of_play_are_ignored() {
+        let mut rules = MatchRules::default();
+        rules.handicaps[Side::Top.index()].starting_points = 3;
+
+        assert!(!has_handicap(&rules, Side::Left));
+
+        rules.mode = GameMode::FourPlayer;
+        assert!(has_handicap(&rules, Side::Left));
+    }
+}
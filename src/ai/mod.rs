@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+use super::{AppState, Ball, Config, Controller, Difficulty, Eliminated, MatchRules, MoveDirection, Paddle, Players, Seat, Side, BALL_RADIUS, move_and_cap_paddle};
+use super::doubles::FrontPaddle;
+use super::net::HostSession;
+
+pub struct AiPlugin;
+
+// How well a computer player plays
+struct Skill {
+    // Fraction of the normal paddle speed
+    speed: f32,
+    // Distance from the target the paddle is happy to stay at
+    dead_zone: f32,
+    // Work out where the ball will cross, walls included, instead of just following it
+    predicts: bool,
+}
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(computer_players)
+        );
+    }
+}
+
+fn skill(difficulty: Difficulty) -> Skill {
+    match difficulty {
+        Difficulty::Easy => Skill { speed: 0.5, dead_zone: 30.0, predicts: false },
+        Difficulty::Normal => Skill { speed: 0.8, dead_zone: 15.0, predicts: false },
+        Difficulty::Hard => Skill { speed: 1.0, dead_zone: 5.0, predicts: true },
+    }
+}
+
+// Computer players go for the closest ball heading their way, and back to the middle when there's none
+fn computer_players(
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    host_session: Option<Res<HostSession>>,
+    ball_query: Query<(&Transform, &Ball), Without<Paddle>>,
+    mut paddle_query: Query<(&Paddle, &mut Transform, Option<&FrontPaddle>), Without<Eliminated>>
+) {
+    // Network matches are played by people on both ends
+    if host_session.is_some() {
+        return;
+    }
+
+    for (paddle, mut transform, front) in paddle_query.iter_mut() {
+        let seat = Seat { side: paddle.side, front: front.is_some() };
+        let skill = match players.0[seat.index()].controller {
+            Controller::Computer(difficulty) => skill(difficulty),
+            Controller::Human => continue
+        };
+
+        let horizontal = paddle.side.is_horizontal();
+        // Positions along the paddle's axis and across it towards the middle of the arena
+        let along = |position: Vec2| if horizontal { position.x } else { position.y };
+        let across = |position: Vec2| match paddle.side {
+            Side::Left => position.x,
+            Side::Right => -position.x,
+            Side::Bottom => position.y,
+            Side::Top => -position.y,
+        };
+        let limit = (if horizontal { config.window_half_width } else { config.window_half_height }) - BALL_RADIUS;
+
+        let paddle_position = transform.translation.truncate();
+
+        let target = ball_query.iter()
+            .filter_map(|(ball_transform, ball)| {
+                let distance = across(ball_transform.translation.truncate()) - across(paddle_position);
+                // Moving away, or already past the paddle
+                let closing_speed = -across(ball.velocity);
+
+                if distance <= 0.0 || closing_speed <= 0.0 {
+                    return None;
+                }
+
+                let position = along(ball_transform.translation.truncate());
+                let crossing = if skill.predicts {
+                    fold(position + along(ball.velocity) * distance / closing_speed, limit)
+                } else {
+                    position
+                };
+
+                Some((distance, crossing))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(0.0, |(_, crossing)| crossing);
+
+        let offset = target - along(paddle_position);
+
+        if offset.abs() <= skill.dead_zone {
+            continue;
+        }
+
+        let direction = match (horizontal, offset > 0.0) {
+            (false, true) => MoveDirection::UP,
+            (false, false) => MoveDirection::DOWN,
+            (true, true) => MoveDirection::RIGHT,
+            (true, false) => MoveDirection::LEFT,
+        };
+        // Stop on the target instead of jittering around it
+        let speed = (rules.paddle_speed(paddle.side) * skill.speed).min(offset.abs());
+
+        move_and_cap_paddle(&config, &mut transform, direction, speed);
+    }
+}
+
+// Bounce a coordinate back and forth between -limit and limit, the way the ball bounces off the walls
+fn fold(position: f32, limit: f32) -> f32 {
+    let wrapped = (position + limit).rem_euclid(limit * 4.0);
+
+    if wrapped <= limit * 2.0 {
+        wrapped - limit
+    } else {
+        limit * 3.0 - wrapped
+    }
+}
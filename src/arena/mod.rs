@@ -0,0 +1,355 @@
+use std::fs;
+use std::path::Path;
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use super::{AppState, Ball, MatchRules, BALL_RADIUS};
+use super::physics;
+
+pub struct ArenaPlugin;
+
+// An arena layout read from assets/levels, the first one is always the bare arena
+pub struct Level {
+    pub name: String,
+    obstacles: Vec<ObstacleDefinition>,
+}
+
+pub struct Levels(pub Vec<Level>);
+
+struct ObstacleDefinition {
+    kind: ObstacleKind,
+    position: Vec3,
+    size: Vec2,
+    // Moving obstacles swing back and forth by this much around their position
+    movement: Option<(Vec2, f32)>,
+}
+
+#[derive(Clone, Copy)]
+enum ObstacleKind {
+    Block,
+    Bumper,
+    Portal { exit: Vec3 },
+}
+
+#[derive(Component)]
+struct Obstacle(ObstacleKind);
+
+#[derive(Component)]
+struct Mover {
+    origin: Vec3,
+    offset: Vec2,
+    period: f32,
+}
+
+// Seconds played in the current match, drives the moving obstacles so they stop while paused
+pub struct ArenaClock(pub f32);
+
+const LEVEL_DIRECTORY: &str = "assets/levels";
+const PORTAL_SIZE: f32 = 50.0;
+// Extra speed given by bumpers on top of the usual bounce acceleration
+const BUMPER_BOOST: f32 = 1.1;
+
+const BLOCK_COLOR: Color = Color::rgb(0.55, 0.55, 0.55);
+const BUMPER_COLOR: Color = Color::rgb(0.92, 0.39, 0.20);
+const PORTAL_COLOR: Color = Color::rgba(0.6, 0.2, 0.8, 0.7);
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Levels(load_levels()))
+        .insert_resource(ArenaClock(0.0))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(setup_arena)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(move_obstacles.label("move_obstacles"))
+                .with_system(obstacle_collisions.after("move_obstacles"))
+        );
+    }
+}
+
+// Level files are read in file name order, broken ones are skipped with a warning
+fn load_levels() -> Vec<Level> {
+    let mut levels = vec![Level { name: "Bare".to_string(), obstacles: Vec::new() }];
+
+    let mut paths: Vec<_> = match fs::read_dir(LEVEL_DIRECTORY) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(err) => {
+            warn!("Could not read {}: {}", LEVEL_DIRECTORY, err);
+            return levels;
+        }
+    };
+    paths.sort();
+
+    for path in paths.iter().filter(|path| path.extension().is_some_and(|extension| extension == "level")) {
+        match load_level(path) {
+            Ok(level) => levels.push(level),
+            Err(err) => warn!("Skipping level {}: {}", path.display(), err),
+        }
+    }
+
+    levels
+}
+
+fn load_level(path: &Path) -> Result<Level, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    parse_level(&path.file_stem().unwrap_or_default().to_string_lossy(), &contents)
+}
+
+// The file name is the name of the level unless the file gives another one
+fn parse_level(file_name: &str, contents: &str) -> Result<Level, String> {
+    let mut level = Level {
+        name: file_name.to_string(),
+        obstacles: Vec::new(),
+    };
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        if keyword == "name" {
+            level.name = rest.trim().to_string();
+            continue;
+        }
+
+        let values = rest.split_whitespace()
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| format!("line {}: invalid number", number + 1))?;
+
+        let expected = match keyword {
+            "block" => 4,
+            "moving" => 7,
+            "bumper" => 3,
+            "portal" => 4,
+            _ => return Err(format!("line {}: unknown obstacle '{}'", number + 1, keyword)),
+        };
+
+        if values.len() != expected {
+            return Err(format!("line {}: {} takes {} numbers", number + 1, keyword, expected));
+        }
+
+        let position = Vec3::new(values[0], values[1], 0.0);
+
+        match keyword {
+            "block" => level.obstacles.push(ObstacleDefinition {
+                kind: ObstacleKind::Block,
+                position,
+                size: Vec2::new(values[2], values[3]),
+                movement: None,
+            }),
+            "moving" => level.obstacles.push(ObstacleDefinition {
+                kind: ObstacleKind::Block,
+                position,
+                size: Vec2::new(values[2], values[3]),
+                movement: Some((Vec2::new(values[4], values[5]), values[6].max(0.1))),
+            }),
+            "bumper" => level.obstacles.push(ObstacleDefinition {
+                kind: ObstacleKind::Bumper,
+                position,
+                size: Vec2::new(values[2], values[2]),
+                movement: None,
+            }),
+            // Portals come in pairs leading to each other
+            _ => {
+                let other_end = Vec3::new(values[2], values[3], 0.0);
+
+                for (position, exit) in [(position, other_end), (other_end, position)] {
+                    level.obstacles.push(ObstacleDefinition {
+                        kind: ObstacleKind::Portal { exit },
+                        position,
+                        size: Vec2::new(PORTAL_SIZE, PORTAL_SIZE),
+                        movement: None,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(level)
+}
+
+fn setup_arena(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    levels: Res<Levels>,
+    rules: Res<MatchRules>,
+    mut clock: ResMut<ArenaClock>,
+    obstacle_query: Query<Entity, With<Obstacle>>
+) {
+    for entity in obstacle_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    clock.0 = 0.0;
+
+    let level = match levels.0.get(rules.level) {
+        Some(level) => level,
+        None => return
+    };
+
+    for definition in level.obstacles.iter() {
+        let mut obstacle = match definition.kind {
+            // Bumpers are round like the ball, but bounce it like a block of the same size
+            ObstacleKind::Bumper => commands.spawn_bundle(MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::UVSphere::default())).into(),
+                transform: Transform {
+                    scale: (definition.size / 2.0).extend(0.0),
+                    translation: definition.position,
+                    ..Default::default()
+                },
+                material: materials.add(ColorMaterial::from(BUMPER_COLOR)),
+                ..Default::default()
+            }),
+            ObstacleKind::Block | ObstacleKind::Portal { .. } => commands.spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: if let ObstacleKind::Portal { .. } = definition.kind { PORTAL_COLOR } else { BLOCK_COLOR },
+                    ..Default::default()
+                },
+                transform: Transform {
+                    scale: definition.size.extend(0.0),
+                    translation: definition.position,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        };
+
+        obstacle.insert(Obstacle(definition.kind));
+
+        if let Some((offset, period)) = definition.movement {
+            obstacle.insert(Mover {
+                origin: definition.position,
+                offset,
+                period,
+            });
+        }
+    }
+}
+
+fn move_obstacles(
+    time: Res<Time>,
+    mut clock: ResMut<ArenaClock>,
+    mut mover_query: Query<(&Mover, &mut Transform)>
+) {
+    clock.0 += time.delta_seconds();
+
+    for (mover, mut transform) in mover_query.iter_mut() {
+        let phase = (clock.0 / mover.period * std::f32::consts::TAU).sin();
+        transform.translation = mover.origin + (mover.offset * phase).extend(0.0);
+    }
+}
+
+fn obstacle_collisions(
+    mut ball_query: Query<(&mut Transform, &mut Ball)>,
+    obstacle_query: Query<(&Transform, &Obstacle), Without<Ball>>
+) {
+    for (mut ball_transform, mut ball) in ball_query.iter_mut() {
+        for (transform, obstacle) in obstacle_query.iter() {
+            match obstacle.0 {
+                // Blocks and bumpers are solid, just like the paddles
+                ObstacleKind::Block => {
+                    physics::bounce_off_paddle(
+                        ball_transform.translation,
+                        &mut ball.velocity,
+                        transform.translation,
+                        transform.scale.truncate() / 2.0
+                    );
+                },
+                ObstacleKind::Bumper => {
+                    let hit = physics::bounce_off_paddle(
+                        ball_transform.translation,
+                        &mut ball.velocity,
+                        transform.translation,
+                        transform.scale.truncate()
+                    );
+
+                    if hit {
+                        ball.velocity *= BUMPER_BOOST;
+                    }
+                },
+                // Come out of the other end going the same way, far enough not to fall straight back in
+                ObstacleKind::Portal { exit } => {
+                    let distance = ball_transform.translation.truncate().distance(transform.translation.truncate());
+
+                    if distance < PORTAL_SIZE / 2.0 {
+                        let direction = ball.velocity.normalize_or_zero();
+                        ball_transform.translation = exit + (direction * (PORTAL_SIZE / 2.0 + BALL_RADIUS + 1.0)).extend(0.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_levels_load() {
+        let levels = load_levels();
+        let names: Vec<&str> = levels.iter().map(|level| level.name.as_str()).collect();
+
+        assert_eq!(names, ["Bare", "Pillars", "Bumpers", "Portals"]);
+        assert!(levels[1..].iter().all(|level| !level.obstacles.is_empty()));
+    }
+
+    #[test]
+    fn level_takes_its_name_from_the_file() {
+        let level = parse_level("1-empty", "# Nothing in here\n\n").unwrap();
+        assert_eq!(level.name, "1-empty");
+        assert!(level.obstacles.is_empty());
+
+        let level = parse_level("1-empty", "name  The void \n").unwrap();
+        assert_eq!(level.name, "The void");
+    }
+
+    #[test]
+    fn obstacles_are_read() {
+        let level = parse_level("test", "block 1 2 3 4\nmoving 0 0 20 80 0 120 0\nbumper -5 6 50\n").unwrap();
+        let [block, moving, bumper] = match level.obstacles.as_slice() {
+            [block, moving, bumper] => [block, moving, bumper],
+            obstacles => panic!("{} obstacles", obstacles.len()),
+        };
+
+        assert!(matches!(block.kind, ObstacleKind::Block));
+        assert_eq!((block.position, block.size, block.movement), (Vec3::new(1.0, 2.0, 0.0), Vec2::new(3.0, 4.0), None));
+
+        // A period of zero would divide by zero
+        assert_eq!(moving.movement, Some((Vec2::new(0.0, 120.0), 0.1)));
+
+        assert!(matches!(bumper.kind, ObstacleKind::Bumper));
+        assert_eq!(bumper.size, Vec2::new(50.0, 50.0));
+    }
+
+    #[test]
+    fn portals_lead_to_each_other() {
+        let level = parse_level("test", "portal -250 200 250 -200").unwrap();
+        let ends: Vec<(Vec3, Vec3)> = level.obstacles.iter()
+            .map(|obstacle| match obstacle.kind {
+                ObstacleKind::Portal { exit } => (obstacle.position, exit),
+                _ => panic!("not a portal"),
+            })
+            .collect();
+
+        let (a, b) = (Vec3::new(-250.0, 200.0, 0.0), Vec3::new(250.0, -200.0, 0.0));
+        assert_eq!(ends, [(a, b), (b, a)]);
+    }
+
+    #[test]
+    fn broken_lines_are_reported() {
+        let error = |contents| parse_level("test", contents).err().unwrap();
+
+        assert_eq!(error("block 0 0 10 10\nwall 0 0"), "line 2: unknown obstacle 'wall'");
+        assert_eq!(error("bumper 0 0"), "line 1: bumper takes 3 numbers");
+        assert_eq!(error("\n\nblock 0 zero 10 10"), "line 3: invalid number");
+    }
+}
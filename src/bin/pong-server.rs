@@ -0,0 +1,3 @@
+fn main() {
+    pong::server::run();
+}
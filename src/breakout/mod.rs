@@ -0,0 +1,167 @@
+use std::fs;
+use bevy::prelude::*;
+use super::{AppState, Ball, Config, GameMode, MatchRules};
+use super::physics;
+
+pub struct BreakoutPlugin;
+
+// Hit points of the left side bricks by row and column, loaded from the layout file
+struct BrickLayout(Vec<Vec<Option<u32>>>);
+
+#[derive(Component)]
+pub struct Brick {
+    pub hit_points: u32,
+}
+
+const LAYOUT_FILE: &str = "assets/breakout.layout";
+const BRICK_WIDTH: f32 = 20.0;
+// The bricks have to fit between the goal and the paddle
+const MAX_COLUMNS: usize = 2;
+// Leaves a thin line between neighbouring bricks
+const BRICK_GAP: f32 = 2.0;
+
+impl Plugin for BreakoutPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(load_layout())
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(setup_bricks)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(brick_collisions)
+        );
+    }
+}
+
+fn load_layout() -> BrickLayout {
+    let contents = match fs::read_to_string(LAYOUT_FILE) {
+        Ok(contents) => contents,
+        Err(err) => {
+            warn!("Could not read {}: {}", LAYOUT_FILE, err);
+            return BrickLayout(Vec::new());
+        }
+    };
+
+    parse_layout(&contents)
+}
+
+// Comments can be indented like the rows
+fn parse_layout(contents: &str) -> BrickLayout {
+    let rows = contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.chars()
+                .take(MAX_COLUMNS)
+                .map(|c| c.to_digit(10).filter(|hit_points| *hit_points > 0))
+                .collect()
+        })
+        .collect();
+
+    BrickLayout(rows)
+}
+
+// Bricks only last for one match, a new one puts all of them back
+fn setup_bricks(
+    mut commands: Commands,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    layout: Res<BrickLayout>,
+    brick_query: Query<Entity, With<Brick>>
+) {
+    for entity in brick_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    if rules.mode != GameMode::Breakout || layout.0.is_empty() {
+        return;
+    }
+
+    let row_height = config.window_half_height * 2.0 / layout.0.len() as f32;
+
+    for (row, columns) in layout.0.iter().enumerate() {
+        let y = config.window_half_height - row_height * (row as f32 + 0.5);
+
+        for (column, hit_points) in columns.iter().enumerate() {
+            let hit_points = match hit_points {
+                Some(hit_points) => *hit_points,
+                None => continue
+            };
+            let x = config.window_half_width - BRICK_WIDTH * (column as f32 + 0.5);
+
+            // Mirrored on the right side
+            for x in [-x, x] {
+                commands.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: brick_color(hit_points),
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        scale: Vec3::new(BRICK_WIDTH - BRICK_GAP, row_height - BRICK_GAP, 0.0),
+                        translation: Vec3::new(x, y, 0.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Brick { hit_points });
+            }
+        }
+    }
+}
+
+// The ball bounces off bricks like off a paddle and takes a hit point each time
+fn brick_collisions(
+    mut commands: Commands,
+    mut ball_query: Query<(&Transform, &mut Ball)>,
+    mut brick_query: Query<(Entity, &Transform, &mut Brick, &mut Sprite)>
+) {
+    for (ball_transform, mut ball) in ball_query.iter_mut() {
+        for (entity, transform, mut brick, mut sprite) in brick_query.iter_mut() {
+            if brick.hit_points == 0 {
+                continue;
+            }
+
+            let hit = physics::bounce_off_paddle(
+                ball_transform.translation,
+                &mut ball.velocity,
+                transform.translation,
+                transform.scale.truncate() / 2.0
+            );
+
+            if !hit {
+                continue;
+            }
+
+            brick.hit_points -= 1;
+
+            if brick.hit_points == 0 {
+                commands.entity(entity).despawn();
+            } else {
+                sprite.color = brick_color(brick.hit_points);
+            }
+        }
+    }
+}
+
+pub fn brick_color(hit_points: u32) -> Color {
+    match hit_points {
+        1 => Color::rgb(0.17, 0.78, 0.19),
+        2 => Color::rgb(0.95, 0.85, 0.2),
+        3 => Color::rgb(0.92, 0.39, 0.20),
+        _ => Color::rgb(1.0, 0.12, 0.11),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indented_comments_and_blank_lines_are_skipped() {
+        let layout = parse_layout("# bricks\n  # indented\n\n   \n 21\n0 3\n");
+
+        assert_eq!(layout.0, vec![vec![Some(2), Some(1)], vec![None, None]]);
+    }
+}
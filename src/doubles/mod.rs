@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use super::{AppState, Config, GameMode, MatchRules, MoveDirection, Paddle, Players, Seat, Side, move_and_cap_paddle};
+
+pub struct DoublesPlugin;
+
+// The paddle closer to the net on either side in doubles, the one at the
+// usual position is the back paddle
+#[derive(Component)]
+pub struct FrontPaddle;
+
+const LEFT_FRONT_KEYS: (KeyCode, KeyCode) = (KeyCode::T, KeyCode::G);
+const RIGHT_FRONT_KEYS: (KeyCode, KeyCode) = (KeyCode::I, KeyCode::K);
+
+impl Plugin for DoublesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(setup_doubles)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(front_paddle_input)
+        );
+    }
+}
+
+// Add or remove the front paddles depending on the mode and put them back in place
+fn setup_doubles(
+    mut commands: Commands,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    mut paddle_query: Query<(Entity, &Paddle, &mut Transform), With<FrontPaddle>>
+) {
+    let doubles = rules.mode == GameMode::Doubles;
+    let mut has_front_paddles = false;
+
+    for (entity, paddle, mut transform) in paddle_query.iter_mut() {
+        if !doubles {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        has_front_paddles = true;
+        transform.translation = front_start_position(&config, paddle.side);
+    }
+
+    if doubles && !has_front_paddles {
+        for side in [Side::Left, Side::Right] {
+            commands.spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1.0, 1.0, 1.0),
+                    ..Default::default()
+                },
+                transform: Transform {
+                    scale: config.paddle_size,
+                    translation: front_start_position(&config, side),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(Paddle { side })
+            .insert(FrontPaddle);
+        }
+    }
+}
+
+fn front_paddle_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    mut paddle_query: Query<(&Paddle, &mut Transform), With<FrontPaddle>>
+) {
+    for (paddle, mut transform) in paddle_query.iter_mut() {
+        if !players.is_human_at(Seat::front(paddle.side)) {
+            continue;
+        }
+
+        let (up, down) = if paddle.side == Side::Left { LEFT_FRONT_KEYS } else { RIGHT_FRONT_KEYS };
+        let speed = rules.paddle_speed(paddle.side);
+
+        if keyboard_input.pressed(up) {
+            move_and_cap_paddle(&config, &mut transform, MoveDirection::UP, speed);
+        }
+
+        if keyboard_input.pressed(down) {
+            move_and_cap_paddle(&config, &mut transform, MoveDirection::DOWN, speed);
+        }
+    }
+}
+
+fn front_start_position(config: &Config, side: Side) -> Vec3 {
+    if side == Side::Left { config.left_front_start_position } else { config.right_front_start_position }
+}
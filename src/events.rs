@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+use super::physics::Side;
+
+// What happens in a match, sent by the collision and scoring systems so sounds,
+// effects, stats and achievements can follow the game without touching the physics
+
+// Paddles are split into this many zones along their length for PaddleHit
+pub const PADDLE_ZONES: usize = 5;
+
+// A paddle sent the ball back
+pub struct PaddleHit {
+    pub paddle: Entity,
+    pub side: Side,
+    // Speed of the ball coming off the paddle
+    pub speed: f32,
+    // Where along the paddle the ball hit, 0 is the bottom end of an upright paddle
+    // and the left end of a flat one
+    pub zone: usize,
+}
+
+// The ball bounced off one of the walls around the arena
+pub struct WallBounce {
+    pub side: Side,
+}
+
+// A ball left the arena through a goal
+pub struct GoalScored {
+    // The goal the ball went through
+    pub side: Side,
+    // Who the point goes to, in the four player mode the last one to touch the ball if it wasn't the
+    // defender. None when nobody gets the credit.
+    pub scorer: Option<Side>,
+}
+
+// The round ended after this many paddle hits, in multi-ball that's when the last ball is gone
+pub struct RallyEnded {
+    pub hits: i32,
+}
+
+// Someone won the match, sent on the tick it's decided
+pub struct MatchWon {
+    pub winner: Side,
+}
+
+// The ball got past in practice, there's nobody to win so it's sent instead of MatchWon
+pub struct RunEnded {
+    pub hits: i32,
+}
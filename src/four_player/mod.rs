@@ -0,0 +1,251 @@
+use bevy::prelude::*;
+use super::{AppState, Config, GameMode, MatchRules, MoveDirection, Paddle, Player3, Player4, Players, ScoreBoard, Side, move_and_cap_paddle};
+
+pub struct FourPlayerPlugin;
+
+// Paddle of a player who ran out of lives, their side of the arena is a wall from now on
+#[derive(Component)]
+pub struct Eliminated;
+
+#[derive(Component)]
+struct SideWall;
+
+// Lives of the top and bottom players, the side players use the normal score texts
+#[derive(Component)]
+struct LivesText(Side);
+
+const WALL_THICKNESS: f32 = 10.0;
+const WALL_COLOR: Color = Color::rgb(0.55, 0.55, 0.55);
+
+impl Plugin for FourPlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_startup_system(setup_lives_text)
+        .add_system(lives_text)
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(setup_four_player.label("setup_four_player"))
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(player3_input)
+                .with_system(player4_input)
+                .with_system(eliminate_players)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Reset)
+                .with_system(eliminate_players)
+        );
+    }
+}
+
+fn setup_lives_text(mut commands: Commands, asset_server: Res<AssetServer>, windows: Res<Windows>) {
+    let window = windows.get_primary().unwrap();
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    for (side, position) in [
+        (Side::Top, Rect { top: Val::Px(60.0), left: Val::Px(window.width() / 2.0 - 10.0), ..Default::default() }),
+        (Side::Bottom, Rect { bottom: Val::Px(60.0), left: Val::Px(window.width() / 2.0 - 10.0), ..Default::default() }),
+    ] {
+        commands.spawn_bundle(TextBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(1.0, 1.0, 1.0),
+                },
+                Default::default()
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position,
+                ..Default::default()
+            },
+            ..Default::default()
+        }).insert(LivesText(side));
+    }
+}
+
+fn lives_text(
+    scoreboard: Res<ScoreBoard>,
+    rules: Res<MatchRules>,
+    mut text_query: Query<(&mut Text, &LivesText)>
+) {
+    for (mut text, lives_text) in text_query.iter_mut() {
+        let value = if rules.mode == GameMode::FourPlayer {
+            format!("{}", scoreboard.lives[lives_text.0.index()])
+        } else {
+            String::new()
+        };
+
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// Bring everyone back for a new match, and add or remove the top and bottom
+// paddles depending on the mode
+fn setup_four_player(
+    mut commands: Commands,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    mut paddle_query: Query<(Entity, &Paddle, &mut Transform, &mut Visibility)>,
+    wall_query: Query<Entity, With<SideWall>>
+) {
+    let four_player = rules.mode == GameMode::FourPlayer;
+    let mut has_extra_paddles = false;
+
+    for entity in wall_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for (entity, paddle, mut transform, mut visibility) in paddle_query.iter_mut() {
+        commands.entity(entity).remove::<Eliminated>();
+        visibility.is_visible = true;
+
+        match paddle.side {
+            Side::Top | Side::Bottom if !four_player => {
+                commands.entity(entity).despawn();
+            },
+            Side::Top => {
+                has_extra_paddles = true;
+                transform.translation = config.player3_start_position;
+            },
+            Side::Bottom => transform.translation = config.player4_start_position,
+            _ => {}
+        }
+    }
+
+    if four_player && !has_extra_paddles {
+        let player3 = spawn_paddle(&mut commands, &config, Side::Top);
+        commands.entity(player3).insert(Player3);
+
+        let player4 = spawn_paddle(&mut commands, &config, Side::Bottom);
+        commands.entity(player4).insert(Player4);
+    }
+}
+
+fn spawn_paddle(commands: &mut Commands, config: &Config, side: Side) -> Entity {
+    let translation = if side == Side::Top { config.player3_start_position } else { config.player4_start_position };
+
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: Color::rgb(1.0, 1.0, 1.0),
+            ..Default::default()
+        },
+        transform: Transform {
+            // Lying flat, the paddle's length is along x
+            scale: Vec3::new(config.paddle_size.y, config.paddle_size.x, config.paddle_size.z),
+            translation,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .insert(Paddle { side })
+    .id()
+}
+
+fn player3_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    mut player3_query: Query<&mut Transform, With<Player3>>
+) {
+    if !players.is_human(Side::Top) {
+        return;
+    }
+
+    let speed = rules.paddle_speed(Side::Top);
+
+    for mut transform in player3_query.iter_mut() {
+        if keyboard_input.pressed(KeyCode::J) {
+            move_and_cap_paddle(&config, &mut transform, MoveDirection::LEFT, speed);
+        }
+
+        if keyboard_input.pressed(KeyCode::L) {
+            move_and_cap_paddle(&config, &mut transform, MoveDirection::RIGHT, speed);
+        }
+    }
+}
+
+fn player4_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    mut player4_query: Query<&mut Transform, With<Player4>>
+) {
+    if !players.is_human(Side::Bottom) {
+        return;
+    }
+
+    let speed = rules.paddle_speed(Side::Bottom);
+
+    for mut transform in player4_query.iter_mut() {
+        if keyboard_input.pressed(KeyCode::Numpad4) {
+            move_and_cap_paddle(&config, &mut transform, MoveDirection::LEFT, speed);
+        }
+
+        if keyboard_input.pressed(KeyCode::Numpad6) {
+            move_and_cap_paddle(&config, &mut transform, MoveDirection::RIGHT, speed);
+        }
+    }
+}
+
+// Hide the paddles of players who are out of lives and wall off their sides
+fn eliminate_players(
+    mut commands: Commands,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    scoreboard: Res<ScoreBoard>,
+    mut paddle_query: Query<(Entity, &Paddle, &mut Visibility), Without<Eliminated>>
+) {
+    if rules.mode != GameMode::FourPlayer {
+        return;
+    }
+
+    for (entity, paddle, mut visibility) in paddle_query.iter_mut() {
+        if scoreboard.lives[paddle.side.index()] > 0 {
+            continue;
+        }
+
+        commands.entity(entity).insert(Eliminated);
+        visibility.is_visible = false;
+
+        let (translation, scale) = match paddle.side {
+            Side::Left => (
+                Vec3::new(-config.window_half_width + WALL_THICKNESS / 2.0, 0.0, 0.0),
+                Vec3::new(WALL_THICKNESS, config.window_half_height * 2.0, 0.0)
+            ),
+            Side::Right => (
+                Vec3::new(config.window_half_width - WALL_THICKNESS / 2.0, 0.0, 0.0),
+                Vec3::new(WALL_THICKNESS, config.window_half_height * 2.0, 0.0)
+            ),
+            Side::Top => (
+                Vec3::new(0.0, config.window_half_height - WALL_THICKNESS / 2.0, 0.0),
+                Vec3::new(config.window_half_width * 2.0, WALL_THICKNESS, 0.0)
+            ),
+            Side::Bottom => (
+                Vec3::new(0.0, -config.window_half_height + WALL_THICKNESS / 2.0, 0.0),
+                Vec3::new(config.window_half_width * 2.0, WALL_THICKNESS, 0.0)
+            ),
+        };
+
+        commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: WALL_COLOR,
+                ..Default::default()
+            },
+            transform: Transform {
+                scale,
+                translation,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(SideWall);
+    }
+}
@@ -0,0 +1,170 @@
+use bevy::prelude::*;
+use super::ui;
+use super::{AppState, GameMode, MatchRules, Players, ScoreBoard, Side, scoreboard_system};
+use super::practice::{PersonalBest, SurvivalTime};
+use super::ratings::RatingChanges;
+use super::tournament::Tournament;
+use super::match_stats::{self, MatchStats};
+
+pub struct GameOverPlugin;
+
+struct GameOverScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for GameOverScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(setup_game_over.after("update_ratings"))
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::GameOver)
+                .with_system(game_over_controls)
+                .with_system(scoreboard_system)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::GameOver)
+                .with_system(ui::cleanup::<GameOverScreen>)
+        );
+    }
+}
+
+fn setup_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    scoreboard: Res<ScoreBoard>,
+    survival_time: Res<SurvivalTime>,
+    best: Res<PersonalBest>,
+    rating_changes: Res<RatingChanges>,
+    tournament: Option<Res<Tournament>>,
+    stats: Res<MatchStats>
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    // Player numbers follow the sides, in doubles that's the team of the side
+    let winner = scoreboard.winner(&rules).and_then(|winner| Side::ALL.get(winner as usize - 1));
+    let title = match (rules.mode, winner) {
+        (GameMode::Practice, _) | (_, None) => "Game over".to_string(),
+        (_, Some(side)) => format!("{} wins!", players.name(rules.mode, *side))
+    };
+
+    let controls_text = if tournament.is_some_and(|tournament| tournament.is_playing()) {
+        "Enter for the bracket, Esc for the menu"
+    } else {
+        "Enter to play again, Esc for the menu"
+    };
+
+    let ui_root = commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text::with_section(
+                title,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 60.0,
+                    color: Color::rgb(0.92, 0.39, 0.20),
+                },
+                Default::default()
+            ),
+            ..Default::default()
+        });
+
+        if rules.mode == GameMode::Practice {
+            let result = if best.beaten {
+                format!("{} hits in {:.1} s, a new personal best!", scoreboard.hits, survival_time.0)
+            } else {
+                format!("{} hits in {:.1} s, personal best {} hits in {:.1} s", scoreboard.hits, survival_time.0, best.hits, best.seconds)
+            };
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    result,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 30.0,
+                        color: Color::rgb(1.0, 1.0, 1.0),
+                    },
+                    Default::default()
+                ),
+                ..Default::default()
+            });
+        }
+
+        if !rating_changes.0.is_empty() {
+            let ratings = rating_changes.0.iter()
+                .map(|change| format!("{} {:.0} ({:+.0})", players.name(rules.mode, change.side), change.rating, change.delta))
+                .collect::<Vec<String>>()
+                .join("   ");
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    ratings,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 30.0,
+                        color: Color::rgb(1.0, 1.0, 1.0),
+                    },
+                    Default::default()
+                ),
+                ..Default::default()
+            });
+        }
+
+        match_stats::spawn_stats(parent, &stats, &rules, &players, &font);
+
+        parent.spawn_bundle(TextBundle {
+            text: Text::with_section(
+                controls_text,
+                TextStyle {
+                    font,
+                    font_size: 20.0,
+                    color: Color::rgb(1.0, 1.0, 1.0),
+                },
+                Default::default()
+            ),
+            ..Default::default()
+        });
+    }).id();
+
+    commands.insert_resource(GameOverScreen { ui_root });
+}
+
+fn game_over_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    tournament: Option<Res<Tournament>>
+) {
+    // A player joining or leaving a hosted match may have changed the state already
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        // Tournament matches go back to the bracket for the next one
+        if tournament.is_some_and(|tournament| tournament.is_playing()) {
+            let _ = app_state.set(AppState::Tournament);
+        } else {
+            let _ = app_state.set(AppState::Restart);
+        }
+
+        keyboard_input.reset(KeyCode::Return);
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        let _ = app_state.set(AppState::Start);
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
@@ -0,0 +1,641 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+use super::{AppState, GameMode, MatchRules, Players, ScoreBoard, Side};
+use super::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use super::replay::{self, ReplayBuffer, SavedReplay};
+use super::storage;
+use super::ui::{self, BUTTON_NORMAL, BUTTON_HOVER, TEXT_COLOR, text_bundle};
+
+pub struct HistoryPlugin;
+
+// A finished match as it's kept in the history file
+#[derive(Serialize, Deserialize)]
+struct MatchRecord {
+    // Seconds since the Unix epoch when the match ended
+    date: u64,
+    mode: String,
+    // Names in the order of the mode's sides
+    players: Vec<String>,
+    winner: Option<String>,
+    score: FinalScore,
+    points: Vec<Point>,
+    // Seconds of play, pauses and replays left out
+    duration: f32,
+    // The last seconds of the match, relative to the save directory
+    replay: Option<String>,
+}
+
+// The scoreboard at game over, without the running state of the match
+#[derive(Default, Serialize, Deserialize)]
+struct FinalScore {
+    player1: i32,
+    player2: i32,
+    // Lives left for each side in the four player mode, indexed by Side
+    lives: [i32; 4],
+    hits: i32,
+    longest_rally: i32,
+}
+
+impl FinalScore {
+    fn new(scoreboard: &ScoreBoard) -> Self {
+        FinalScore {
+            player1: scoreboard.player1,
+            player2: scoreboard.player2,
+            lives: scoreboard.lives,
+            hits: scoreboard.hits,
+            longest_rally: scoreboard.longest_rally,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Point {
+    // Seconds into the match
+    time: f32,
+    description: String,
+}
+
+struct MatchHistory {
+    // Oldest match first
+    records: Vec<MatchRecord>,
+    // A file that couldn't be read and couldn't be moved away is left alone
+    writable: bool,
+}
+
+// The match being played, turned into a record at game over
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct MatchLog {
+    elapsed: f32,
+    points: Vec<Point>,
+    // Points and lives on the previous tick, to notice when they change
+    last_score: Option<(i32, i32, [i32; 4])>,
+}
+
+// What the history screen is showing
+#[derive(Default)]
+struct HistoryView {
+    // Only matches this player was in, or all of them
+    player: Option<String>,
+    // Index into the history of the match opened in detail
+    selected: Option<usize>,
+    // Replay of the selected match, while it's being watched
+    replay: Option<SavedReplay>,
+}
+
+// Frame of the replay being watched, kept apart from the view so playing it doesn't rebuild the screen
+#[derive(Default)]
+struct ReplayCursor(usize);
+
+// One of the boxes standing in for the balls and paddles of the replay
+#[derive(Component)]
+struct ReplayShape(usize);
+
+struct HistoryScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for HistoryScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+enum HistoryButton {
+    Player,
+    Match(usize),
+    Replay,
+    Back,
+}
+
+const HISTORY_FILE: &str = "history";
+// Written at the start of the file, bumped whenever MatchRecord changes
+const HISTORY_VERSION: u32 = 2;
+const REPLAY_DIRECTORY: &str = "replays";
+// Matches listed at once, newest first
+const LIST_LENGTH: usize = 10;
+// Size of the replay arena next to the real one
+const REPLAY_SCALE: f32 = 0.5;
+// Most balls and paddles drawn at once
+const REPLAY_SHAPES: usize = 16;
+
+const ROW_NORMAL: Color = Color::rgb(0.2, 0.2, 0.2);
+const ROW_HOVER: Color = Color::rgb(0.3, 0.3, 0.3);
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(load_history())
+        .init_resource::<MatchLog>()
+        .init_resource::<HistoryView>()
+        .init_resource::<ReplayCursor>()
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(clear_log)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                // The winning point is scored in move_ball on the same tick
+                .with_system(log_points.after("move_ball"))
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(record_match)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::History)
+                .with_system(setup_history_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::History)
+                .with_system(history_buttons.label("history_buttons"))
+                .with_system(history_controls.label("history_controls"))
+                .with_system(refresh_screen.after("history_buttons").after("history_controls"))
+                .with_system(play_replay)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::History)
+                .with_system(ui::cleanup::<HistoryScreen>)
+        );
+    }
+}
+
+// The history keeps growing, so it's stored in binary instead of the line based text of the other files
+fn load_history() -> MatchHistory {
+    let bytes = match storage::load_bytes(HISTORY_FILE) {
+        Some(bytes) => bytes,
+        None => return MatchHistory { records: Vec::new(), writable: true }
+    };
+
+    match decode_history(&bytes) {
+        Ok(records) => MatchHistory { records, writable: true },
+        Err(err) => {
+            warn!("Could not read the match history in {}: {}", HISTORY_FILE, err);
+            // Starting over is fine once the old history is kept somewhere else
+            MatchHistory { records: Vec::new(), writable: storage::back_up(HISTORY_FILE) }
+        }
+    }
+}
+
+fn decode_history(bytes: &[u8]) -> Result<Vec<MatchRecord>, String> {
+    let version: u32 = bincode::deserialize(bytes).map_err(|err| err.to_string())?;
+
+    if version != HISTORY_VERSION {
+        return Err(format!("unknown version {}", version));
+    }
+
+    let (_, records): (u32, Vec<MatchRecord>) = bincode::deserialize(bytes).map_err(|err| err.to_string())?;
+    Ok(records)
+}
+
+fn encode_history(records: &[MatchRecord]) -> Vec<u8> {
+    bincode::serialize(&(HISTORY_VERSION, records)).unwrap()
+}
+
+fn clear_log(mut log: ResMut<MatchLog>) {
+    *log = MatchLog::default();
+}
+
+fn log_points(
+    time: Res<Time>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    scoreboard: Res<ScoreBoard>,
+    mut log: ResMut<MatchLog>
+) {
+    log.elapsed += time.delta_seconds();
+
+    let score = (scoreboard.player1, scoreboard.player2, scoreboard.lives);
+
+    let (player1, player2, lives) = match log.last_score.replace(score) {
+        Some(last) if last != score => last,
+        _ => return
+    };
+
+    let mode = rules.mode;
+    let description = if scoreboard.player1 > player1 {
+        format!("{} scores, {}-{}", players.name(mode, Side::Left), scoreboard.player1, scoreboard.player2)
+    } else if scoreboard.player2 > player2 {
+        format!("{} scores, {}-{}", players.name(mode, Side::Right), scoreboard.player1, scoreboard.player2)
+    } else {
+        // Four players lose lives instead
+        match Side::ALL.iter().find(|side| scoreboard.lives[side.index()] < lives[side.index()]) {
+            Some(side) if scoreboard.lives[side.index()] <= 0 => format!("{} is out", players.name(mode, *side)),
+            Some(side) => format!("{} loses a life", players.name(mode, *side)),
+            None => return
+        }
+    };
+
+    let time = log.elapsed;
+    log.points.push(Point { time, description });
+}
+
+// Practice runs keep their own record instead
+fn record_match(
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    scoreboard: Res<ScoreBoard>,
+    replay_buffer: Res<ReplayBuffer>,
+    mut log: ResMut<MatchLog>,
+    mut history: ResMut<MatchHistory>
+) {
+    if rules.mode == GameMode::Practice {
+        return;
+    }
+
+    let date = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+
+    let replay = if replay_buffer.is_empty() {
+        None
+    } else {
+        let file_name = format!("{}/{}.replay", REPLAY_DIRECTORY, date);
+        replay_buffer.save(&file_name);
+        Some(file_name)
+    };
+
+    history.records.push(MatchRecord {
+        date,
+        mode: rules.mode.name().to_string(),
+        players: rules.mode.sides().iter().map(|side| players.name(rules.mode, *side)).collect(),
+        winner: scoreboard.winner(&rules).map(|winner| players.name(rules.mode, Side::ALL[winner as usize - 1])),
+        score: FinalScore::new(&scoreboard),
+        points: std::mem::take(&mut log.points),
+        duration: log.elapsed,
+        replay,
+    });
+
+    if history.writable {
+        storage::save(HISTORY_FILE, encode_history(&history.records));
+    }
+}
+
+// Dates are shown in UTC, the standard library doesn't know the local time zone
+fn format_date(date: u64) -> String {
+    let days = (date / 86400) as i64;
+    let minutes = date % 86400 / 60;
+
+    // Days to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// Points for two sides, lives left for four
+fn format_score(record: &MatchRecord) -> String {
+    let score = &record.score;
+
+    match record.players.as_slice() {
+        [left, right] => format!("{} {} - {} {}", left, score.player1, score.player2, right),
+        players => players.iter()
+            .zip(score.lives.iter())
+            .map(|(name, lives)| format!("{} {}", name, (*lives).max(0)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+fn setup_history_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    history: Res<MatchHistory>,
+    mut view: ResMut<HistoryView>
+) {
+    view.selected = None;
+    view.replay = None;
+
+    let ui_root = spawn_screen(&mut commands, &asset_server, &history, &view);
+    commands.insert_resource(HistoryScreen { ui_root });
+}
+
+fn refresh_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    history: Res<MatchHistory>,
+    view: Res<HistoryView>,
+    mut screen: ResMut<HistoryScreen>
+) {
+    if !view.is_changed() {
+        return;
+    }
+
+    commands.entity(screen.ui_root).despawn_recursive();
+    screen.ui_root = spawn_screen(&mut commands, &asset_server, &history, &view);
+}
+
+fn spawn_screen(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    history: &MatchHistory,
+    view: &HistoryView
+) -> Entity {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        match view.selected.and_then(|index| history.records.get(index)) {
+            Some(record) => spawn_details(parent, record, view, &font),
+            None => spawn_list(parent, history, view, &font),
+        }
+
+        spawn_button(parent, "Back", HistoryButton::Back, BUTTON_NORMAL, 150.0, &font);
+    }).id()
+}
+
+fn spawn_list(parent: &mut ChildBuilder, history: &MatchHistory, view: &HistoryView, font: &Handle<Font>) {
+    parent.spawn_bundle(text_bundle("History", 50.0, TEXT_COLOR, font));
+
+    let player_text = format!("Player: {}", view.player.as_deref().unwrap_or("All"));
+    spawn_button(parent, &player_text, HistoryButton::Player, BUTTON_NORMAL, 300.0, font);
+
+    let mut matches = history.records.iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, record)| view.player.as_ref().map_or(true, |player| record.players.contains(player)))
+        .peekable();
+
+    if matches.peek().is_none() {
+        parent.spawn_bundle(text_bundle("No matches played yet", 20.0, TEXT_COLOR, font));
+    }
+
+    for (index, record) in matches.take(LIST_LENGTH) {
+        let text = format!(
+            "{}   {}   {}   {}",
+            format_date(record.date),
+            record.mode,
+            format_score(record),
+            format_time(record.duration)
+        );
+
+        spawn_button(parent, &text, HistoryButton::Match(index), ROW_NORMAL, 800.0, font);
+    }
+}
+
+fn spawn_details(parent: &mut ChildBuilder, record: &MatchRecord, view: &HistoryView, font: &Handle<Font>) {
+    let title = match &record.winner {
+        Some(winner) => format!("{} wins", winner),
+        None => "Game over".to_string(),
+    };
+
+    parent.spawn_bundle(text_bundle(&title, 50.0, TEXT_COLOR, font));
+    parent.spawn_bundle(text_bundle(&format!("{}, {}", record.mode, format_date(record.date)), 20.0, TEXT_COLOR, font));
+    parent.spawn_bundle(text_bundle(&format_score(record), 30.0, TEXT_COLOR, font));
+    parent.spawn_bundle(text_bundle(
+        &format!(
+            "Played for {}, {} hits, longest rally {}",
+            format_time(record.duration),
+            record.score.hits,
+            record.score.longest_rally
+        ),
+        20.0,
+        TEXT_COLOR,
+        font
+    ));
+
+    if view.replay.is_some() {
+        spawn_replay_arena(parent);
+        return;
+    }
+
+    if record.replay.is_some() {
+        spawn_button(parent, "Watch replay", HistoryButton::Replay, BUTTON_NORMAL, 300.0, font);
+    }
+
+    // Wraps into columns so long four player matches still fit
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(90.0), Val::Auto),
+            flex_wrap: FlexWrap::Wrap,
+            justify_content: JustifyContent::Center,
+            margin: Rect::all(Val::Px(10.0)),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for point in record.points.iter() {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(300.0), Val::Px(25.0)),
+                    ..Default::default()
+                },
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                let text = format!("{}  {}", format_time(point.time), point.description);
+                parent.spawn_bundle(text_bundle(&text, 16.0, TEXT_COLOR, font));
+            });
+        }
+    });
+}
+
+// A scaled down arena, the shapes in it are moved by play_replay
+fn spawn_replay_arena(parent: &mut ChildBuilder) {
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(WINDOW_WIDTH * REPLAY_SCALE), Val::Px(WINDOW_HEIGHT * REPLAY_SCALE)),
+            margin: Rect::all(Val::Px(10.0)),
+            ..Default::default()
+        },
+        color: Color::BLACK.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for index in 0..REPLAY_SHAPES {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .insert(ReplayShape(index));
+        }
+    });
+}
+
+// Loops the replay of the match opened in detail
+fn play_replay(
+    view: Res<HistoryView>,
+    mut cursor: ResMut<ReplayCursor>,
+    mut shape_query: Query<(&ReplayShape, &mut Style, &mut UiColor)>
+) {
+    let frames = match &view.replay {
+        Some(replay) if !replay.frames.is_empty() => &replay.frames,
+        _ => return
+    };
+
+    let frame = &frames[cursor.0 % frames.len()];
+    cursor.0 += 1;
+
+    for (shape, mut style, mut color) in shape_query.iter_mut() {
+        match frame.get(shape.0) {
+            Some(saved) => {
+                let [x, y] = saved.position;
+                let [width, height] = saved.size;
+
+                // The arena is centred on the origin, UI nodes are placed from the bottom left
+                style.position = Rect {
+                    left: Val::Px((x - width / 2.0 + WINDOW_WIDTH / 2.0) * REPLAY_SCALE),
+                    bottom: Val::Px((y - height / 2.0 + WINDOW_HEIGHT / 2.0) * REPLAY_SCALE),
+                    ..Default::default()
+                };
+                style.size = Size::new(Val::Px(width * REPLAY_SCALE), Val::Px(height * REPLAY_SCALE));
+                *color = TEXT_COLOR.into();
+            },
+            None => *color = Color::NONE.into()
+        }
+    }
+}
+
+fn history_buttons(
+    history: Res<MatchHistory>,
+    mut app_state: ResMut<State<AppState>>,
+    mut view: ResMut<HistoryView>,
+    mut cursor: ResMut<ReplayCursor>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &HistoryButton),
+        (Changed<Interaction>, With<Button>)>
+) {
+    for (interaction, mut color, history_button) in interaction_query.iter_mut() {
+        let (normal, hover) = match history_button {
+            HistoryButton::Match(_) => (ROW_NORMAL, ROW_HOVER),
+            _ => (BUTTON_NORMAL, BUTTON_HOVER)
+        };
+
+        match *interaction {
+            Interaction::Hovered => *color = hover.into(),
+            Interaction::None => *color = normal.into(),
+            Interaction::Clicked => match *history_button {
+                HistoryButton::Player => view.player = next_player(&history, view.player.as_deref()),
+                HistoryButton::Match(index) => view.selected = Some(index),
+                HistoryButton::Replay => {
+                    let file_name = view.selected.and_then(|index| history.records[index].replay.as_ref());
+                    view.replay = file_name.and_then(|file_name| replay::load_saved(file_name));
+                    cursor.0 = 0;
+                },
+                HistoryButton::Back => back(&mut app_state, &mut view),
+            }
+        }
+    }
+}
+
+fn history_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut view: ResMut<HistoryView>
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        back(&mut app_state, &mut view);
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+// From the replay back to the details, from there back to the list, and then back to the menu
+fn back(app_state: &mut State<AppState>, view: &mut HistoryView) {
+    if view.replay.is_some() {
+        view.replay = None;
+    } else if view.selected.is_some() {
+        view.selected = None;
+    } else {
+        let _ = app_state.set(AppState::Start);
+    }
+}
+
+// Everyone who has played, in alphabetical order, and then all of them again
+fn next_player(history: &MatchHistory, current: Option<&str>) -> Option<String> {
+    let mut names: Vec<&String> = history.records.iter().flat_map(|record| record.players.iter()).collect();
+    names.sort();
+    names.dedup();
+
+    match current {
+        Some(current) => names.into_iter().find(|name| name.as_str() > current).cloned(),
+        None => names.first().map(|name| name.to_string()),
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, history_button: HistoryButton, color: Color, width: f32, font: &Handle<Font>) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(width), Val::Px(40.0)),
+            margin: Rect::all(Val::Px(5.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: color.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle(text, 20.0, TEXT_COLOR, font));
+    })
+    .insert(history_button);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_shown_in_utc() {
+        assert_eq!(format_date(0), "1970-01-01 00:00");
+        assert_eq!(format_date(1700000000), "2023-11-14 22:13");
+        assert_eq!(format_date(1767225599), "2025-12-31 23:59");
+    }
+
+    #[test]
+    fn leap_days_are_counted() {
+        assert_eq!(format_date(951782400), "2000-02-29 00:00");
+        assert_eq!(format_date(1709210040), "2024-02-29 12:34");
+        // 2100 isn't a leap year
+        assert_eq!(format_date(4107542400), "2100-03-01 00:00");
+    }
+
+    #[test]
+    fn history_reads_back_what_it_wrote() {
+        let record = MatchRecord {
+            date: 1700000000,
+            mode: "Classic".to_string(),
+            players: vec!["Ann".to_string(), "Bob".to_string()],
+            winner: Some("Bob".to_string()),
+            score: FinalScore { player1: 3, player2: 5, ..Default::default() },
+            points: vec![Point { time: 12.5, description: "Bob scores, 0-1".to_string() }],
+            duration: 95.0,
+            replay: Some("replays/1700000000.replay".to_string()),
+        };
+
+        let records = decode_history(&encode_history(&[record])).unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].players, ["Ann", "Bob"]);
+        assert_eq!(records[0].winner.as_deref(), Some("Bob"));
+        assert_eq!((records[0].score.player1, records[0].score.player2), (3, 5));
+        assert_eq!(records[0].points[0].description, "Bob scores, 0-1");
+        assert_eq!(records[0].replay.as_deref(), Some("replays/1700000000.replay"));
+    }
+
+    #[test]
+    fn other_versions_are_not_read() {
+        let newer = bincode::serialize(&(HISTORY_VERSION + 1, Vec::<MatchRecord>::new())).unwrap();
+
+        assert!(decode_history(&newer).is_err());
+        assert!(decode_history(&[1, 2]).is_err());
+        assert!(decode_history(&encode_history(&[])).unwrap().is_empty());
+    }
+}
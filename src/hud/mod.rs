@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use super::{AppState, Ball, ScoreBoard};
+use super::events::{PaddleHit, RallyEnded};
+
+pub struct HudPlugin;
+
+#[derive(Component)]
+struct RallyText;
+
+#[derive(Component)]
+struct PointSummaryText;
+
+// Fastest the ball has come off a paddle in the current rally
+struct RallyTopSpeed(f32);
+
+// Rally length and top speed of the last round, shown during its replay or until the next rally gets going
+struct PointSummary(String);
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(RallyTopSpeed(0.0))
+        .insert_resource(PointSummary(String::new()))
+        .add_startup_system(setup_hud)
+        .add_system(rally_text)
+        .add_system(summarize_points.label("summarize_points"))
+        .add_system(point_summary_text.after("summarize_points"))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(clear_rally)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Replay)
+                .with_system(clear_summary)
+        )
+        .add_system_set(
+            // The game over screen has the whole match to show instead
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(clear_summary)
+        );
+    }
+}
+
+fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>, windows: Res<Windows>) {
+    let window = windows.get_primary().unwrap();
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    // Next to player 1's score, ending a little left of it
+    commands.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 20.0,
+                color: Color::rgb(0.55, 0.55, 0.55),
+            },
+            Default::default()
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(22.0),
+                right: Val::Px(window.width() / 2.0 + 100.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(RallyText);
+
+    // Centered across the whole window
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(120.0),
+                left: Val::Px(0.0),
+                ..Default::default()
+            },
+            size: Size::new(Val::Percent(100.0), Val::Px(40.0)),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font,
+                    font_size: 30.0,
+                    color: Color::rgb(0.92, 0.39, 0.20),
+                },
+                Default::default()
+            ),
+            ..Default::default()
+        }).insert(PointSummaryText);
+    });
+}
+
+fn clear_rally(mut top_speed: ResMut<RallyTopSpeed>, mut summary: ResMut<PointSummary>) {
+    top_speed.0 = 0.0;
+    summary.0.clear();
+}
+
+fn clear_summary(mut summary: ResMut<PointSummary>) {
+    summary.0.clear();
+}
+
+// The speed shown is the fastest ball in play, there can be a few in multi-ball
+fn rally_text(
+    scoreboard: Res<ScoreBoard>,
+    ball_query: Query<&Ball>,
+    mut text_query: Query<&mut Text, With<RallyText>>
+) {
+    let speed = ball_query.iter().map(|ball| ball.velocity.length()).fold(0.0, f32::max);
+    let value = format!("Rally {}   Speed {:.0}   Longest {}", scoreboard.rally, speed, scoreboard.longest_rally);
+
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+fn summarize_points(
+    mut paddle_hits: EventReader<PaddleHit>,
+    mut rallies_ended: EventReader<RallyEnded>,
+    mut top_speed: ResMut<RallyTopSpeed>,
+    mut summary: ResMut<PointSummary>
+) {
+    for hit in paddle_hits.iter() {
+        top_speed.0 = top_speed.0.max(hit.speed);
+        // Without a replay the summary stays up until the ball is back in play
+        summary.0.clear();
+    }
+
+    for rally in rallies_ended.iter() {
+        summary.0 = match rally.hits {
+            0 => "No rally".to_string(),
+            1 => format!("Rally of 1 hit, top speed {:.0}", top_speed.0),
+            hits => format!("Rally of {} hits, top speed {:.0}", hits, top_speed.0),
+        };
+        top_speed.0 = 0.0;
+    }
+}
+
+fn point_summary_text(summary: Res<PointSummary>, mut text_query: Query<&mut Text, With<PointSummaryText>>) {
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != summary.0 {
+            text.sections[0].value = summary.0.clone();
+        }
+    }
+}
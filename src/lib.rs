@@ -0,0 +1,1163 @@
+use std::cmp::Ordering;
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+
+mod physics;
+use physics::Side;
+
+mod sim;
+
+mod storage;
+mod ui;
+
+mod events;
+use events::{GoalScored, MatchWon, PaddleHit, RallyEnded, RunEnded, WallBounce, PADDLE_ZONES};
+
+pub mod server;
+
+mod main_menu;
+use main_menu::MainMenuPlugin;
+
+mod game_over;
+use game_over::GameOverPlugin;
+
+mod replay;
+use replay::{ReplayPlugin, ReplaySettings, ReplayBuffer};
+
+mod net;
+use net::{NetPlugin, RemoteInput};
+
+mod rollback;
+use rollback::RollbackPlugin;
+
+mod power_ups;
+use power_ups::{PowerUpPlugin, PowerUp};
+
+mod four_player;
+use four_player::{FourPlayerPlugin, Eliminated};
+
+mod doubles;
+use doubles::{DoublesPlugin, FrontPaddle};
+
+mod arena;
+use arena::ArenaPlugin;
+
+mod breakout;
+use breakout::BreakoutPlugin;
+
+mod practice;
+use practice::PracticePlugin;
+
+mod time_attack;
+use time_attack::TimeAttackPlugin;
+
+mod setup;
+use setup::SetupPlugin;
+
+mod ai;
+use ai::AiPlugin;
+
+mod profiles;
+use profiles::ProfilesPlugin;
+
+mod history;
+use history::HistoryPlugin;
+
+mod ratings;
+use ratings::RatingsPlugin;
+
+mod tournament;
+use tournament::TournamentPlugin;
+
+mod achievements;
+use achievements::AchievementsPlugin;
+
+mod hud;
+use hud::HudPlugin;
+
+mod match_stats;
+use match_stats::MatchStatsPlugin;
+
+mod saved_match;
+use saved_match::SavedMatchPlugin;
+
+#[derive(Component)]
+struct Player1;
+
+#[derive(Component)]
+struct Player2;
+
+#[derive(Component)]
+struct Player3;
+
+#[derive(Component)]
+struct Player4;
+
+#[derive(Component)]
+struct Player1ScoreText;
+
+#[derive(Component)]
+struct Player2ScoreText;
+
+#[derive(Component)]
+struct Paddle {
+    // The side of the arena the paddle defends
+    side: Side,
+}
+
+#[derive(Component)]
+struct Ball {
+    velocity: Vec2,
+    // The paddle that touched the ball last, it collects any power-up the ball passes through
+    last_paddle: Option<Entity>,
+    // Speed power-ups that changed the velocity, each one is undone when it runs out
+    speed_effects: Vec<PowerUp>,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct ScoreBoard {
+    player1: i32,
+    player2: i32,
+    // Lives left for each side in the four player mode, indexed by Side
+    lives: [i32; 4],
+    // Paddle hits this match, the score of a practice run
+    hits: i32,
+    // Seconds left on the match clock in time attack
+    time_left: f32,
+    // Paddle hits since the last point
+    rally: i32,
+    longest_rally: i32,
+    // Fastest ball coming off each side's paddles, indexed by Side
+    fastest_hits: [f32; 4],
+}
+
+impl ScoreBoard {
+    // Number of the player who won the match, if anyone has yet
+    fn winner(&self, rules: &MatchRules) -> Option<i32> {
+        if rules.mode == GameMode::FourPlayer {
+            // Last one standing
+            let mut alive = self.lives.iter().enumerate().filter(|(_, lives)| **lives > 0);
+
+            return match (alive.next(), alive.next()) {
+                (Some((index, _)), None) => Some(index as i32 + 1),
+                _ => None
+            };
+        }
+
+        // A practice run is over as soon as the ball gets past
+        if rules.mode == GameMode::Practice {
+            return if self.player2 > 0 { Some(1) } else { None };
+        }
+
+        // Whoever leads when the clock runs out wins, a tie goes on until the next point
+        if rules.mode == GameMode::TimeAttack {
+            if self.time_left > 0.0 {
+                return None;
+            }
+
+            return match self.player1.cmp(&self.player2) {
+                Ordering::Greater => Some(1),
+                Ordering::Less => Some(2),
+                Ordering::Equal => None
+            };
+        }
+
+        if self.player1 >= rules.points_to_win {
+            Some(1)
+        } else if self.player2 >= rules.points_to_win {
+            Some(2)
+        } else {
+            None
+        }
+    }
+
+    // Sides of the arena the ball can leave through, the rest are walls
+    fn goals(&self, rules: &MatchRules) -> [bool; 4] {
+        match rules.mode {
+            GameMode::FourPlayer => self.lives.map(|lives| lives > 0),
+            // The right side is a wall in practice
+            GameMode::Practice => [true, false, false, false],
+            _ => physics::CLASSIC_GOALS
+        }
+    }
+
+    // A ball left the arena through `side`
+    fn goal(&mut self, rules: &MatchRules, side: Side) {
+        match rules.mode {
+            GameMode::FourPlayer => {
+                let lives = &mut self.lives[side.index()];
+                *lives = (*lives - 1).max(0);
+            },
+            _ => match side {
+                Side::Left => self.player2 += 1,
+                Side::Right => self.player1 += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct MatchRules {
+    points_to_win: i32,
+    mode: GameMode,
+    // Index into arena::Levels, 0 is the bare arena
+    level: usize,
+    // Indexed by Side, teammates in doubles share the handicap of their side
+    handicaps: [Handicap; 4],
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules { points_to_win: 10, mode: GameMode::Classic, level: 0, handicaps: [Handicap::default(); 4] }
+    }
+}
+
+impl MatchRules {
+    // Length of the paddles defending `side`, power-ups scale this further
+    fn paddle_length(&self, config: &Config, side: Side) -> f32 {
+        config.paddle_size.y * self.handicaps[side.index()].paddle_height
+    }
+
+    // Distance the paddles defending `side` move in a frame
+    fn paddle_speed(&self, side: Side) -> f32 {
+        PADDLE_SPEED * self.handicaps[side.index()].paddle_speed
+    }
+}
+
+// Evens out matches between players of different skill
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Handicap {
+    // Multiplies the standard paddle length of Config::paddle_size
+    paddle_height: f32,
+    // Multiplies PADDLE_SPEED
+    paddle_speed: f32,
+    // Points the player starts the match with, or extra lives in the four player mode
+    starting_points: i32,
+}
+
+impl Default for Handicap {
+    fn default() -> Self {
+        Handicap { paddle_height: 1.0, paddle_speed: 1.0, starting_points: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum GameMode {
+    Classic,
+    MultiBall,
+    FourPlayer,
+    Doubles,
+    Breakout,
+    Practice,
+    TimeAttack,
+}
+
+impl GameMode {
+    // Balls put in play at the start of every round
+    fn balls_per_round(self) -> usize {
+        match self {
+            GameMode::Classic | GameMode::FourPlayer | GameMode::Doubles | GameMode::Breakout | GameMode::Practice | GameMode::TimeAttack => 1,
+            GameMode::MultiBall => 3,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::MultiBall => "Multi-ball",
+            GameMode::FourPlayer => "Four players",
+            GameMode::Doubles => "Doubles",
+            GameMode::Breakout => "Breakout",
+            GameMode::Practice => "Practice",
+            GameMode::TimeAttack => "Time attack",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<GameMode> {
+        let mut mode = GameMode::Classic;
+
+        loop {
+            if mode.name() == name {
+                return Some(mode);
+            }
+
+            mode = mode.next();
+
+            if mode == GameMode::Classic {
+                return None;
+            }
+        }
+    }
+
+    // Sides with someone playing on them
+    fn sides(self) -> &'static [Side] {
+        match self {
+            GameMode::Practice => &[Side::Left],
+            GameMode::FourPlayer => &Side::ALL,
+            _ => &[Side::Left, Side::Right],
+        }
+    }
+
+    // Players in the mode, doubles adds a front paddle to each side
+    fn seats(self) -> Vec<Seat> {
+        let mut seats: Vec<Seat> = self.sides().iter().map(|side| Seat::back(*side)).collect();
+
+        if self == GameMode::Doubles {
+            seats.extend([Seat::front(Side::Left), Seat::front(Side::Right)]);
+        }
+
+        seats
+    }
+
+    // The mode after this one on the menu's mode button
+    fn next(self) -> Self {
+        match self {
+            GameMode::Classic => GameMode::MultiBall,
+            GameMode::MultiBall => GameMode::FourPlayer,
+            GameMode::FourPlayer => GameMode::Doubles,
+            GameMode::Doubles => GameMode::Breakout,
+            GameMode::Breakout => GameMode::Practice,
+            GameMode::Practice => GameMode::TimeAttack,
+            GameMode::TimeAttack => GameMode::Classic,
+        }
+    }
+}
+
+// Where a player is: the paddle of a side, or in doubles also the front paddle of the left or right team
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Seat {
+    side: Side,
+    front: bool,
+}
+
+impl Seat {
+    const ALL: [Seat; 6] = [
+        Seat::back(Side::Left),
+        Seat::back(Side::Right),
+        Seat::back(Side::Top),
+        Seat::back(Side::Bottom),
+        Seat::front(Side::Left),
+        Seat::front(Side::Right),
+    ];
+
+    const fn back(side: Side) -> Seat {
+        Seat { side, front: false }
+    }
+
+    const fn front(side: Side) -> Seat {
+        Seat { side, front: true }
+    }
+
+    // The back paddles come first, so their index is the one of their side
+    fn index(self) -> usize {
+        if self.front { Side::ALL.len() + self.side.index() } else { self.side.index() }
+    }
+}
+
+// Who plays on each paddle of the arena, indexed by Seat
+#[derive(Clone, Serialize, Deserialize)]
+struct Players([PlayerSettings; 6]);
+
+impl Players {
+    // The chosen name, or the default one for the mode
+    fn name(&self, mode: GameMode, side: Side) -> String {
+        // Doubles is played by teams, named after the players who have a name
+        if mode == GameMode::Doubles {
+            let names: Vec<&str> = [Seat::back(side), Seat::front(side)].iter()
+                .map(|seat| self.0[seat.index()].name.as_str())
+                .filter(|name| !name.is_empty())
+                .collect();
+
+            return if names.is_empty() { format!("Team {}", side.index() + 1) } else { names.join(" & ") };
+        }
+
+        let name = &self.0[side.index()].name;
+
+        if !name.is_empty() {
+            return name.clone();
+        }
+
+        format!("Player {}", side.index() + 1)
+    }
+
+    // The name of one player of a team, where name() gives the whole team's
+    fn seat_name(&self, mode: GameMode, seat: Seat) -> String {
+        let name = &self.0[seat.index()].name;
+
+        match mode {
+            GameMode::Doubles if name.is_empty() => format!("Team {} {}", seat.side.index() + 1, if seat.front { "front" } else { "back" }),
+            GameMode::Doubles => name.clone(),
+            _ => self.name(mode, seat.side)
+        }
+    }
+
+    fn is_human(&self, side: Side) -> bool {
+        self.is_human_at(Seat::back(side))
+    }
+
+    fn is_human_at(&self, seat: Seat) -> bool {
+        self.0[seat.index()].controller == Controller::Human
+    }
+}
+
+impl Default for Players {
+    fn default() -> Self {
+        let mut players = Players(Default::default());
+        players.0[Side::Right.index()].keys = Keys::Arrows;
+        players
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PlayerSettings {
+    // Empty until the player picks one, see Players::name. Named players have a profile.
+    name: String,
+    controller: Controller,
+    // Index into PADDLE_COLORS
+    color: usize,
+    // Only for the back paddles on the left and right, the rest have their own keys
+    keys: Keys,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        PlayerSettings { name: String::new(), controller: Controller::Human, color: 0, keys: Keys::WS }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Keys {
+    WS,
+    Arrows,
+}
+
+impl Keys {
+    const ALL: [Keys; 2] = [Keys::WS, Keys::Arrows];
+
+    fn up_down(self) -> (KeyCode, KeyCode) {
+        match self {
+            Keys::WS => (KeyCode::W, KeyCode::S),
+            Keys::Arrows => (KeyCode::Up, KeyCode::Down),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Keys::WS => "W/S",
+            Keys::Arrows => "Arrows",
+        }
+    }
+
+    // How the keys are written in the save files
+    fn id(self) -> &'static str {
+        match self {
+            Keys::WS => "ws",
+            Keys::Arrows => "arrows",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Keys> {
+        Keys::ALL.into_iter().find(|keys| keys.id() == id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Controller {
+    Human,
+    Computer(Difficulty),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+// Seed for the serves of the next round. A seed is kept instead of the generator itself
+// so a saved match serves the same balls after it's resumed.
+struct MatchSeed(u64);
+
+// Every ball looks the same, so extra balls can be spawned mid-round
+struct BallAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<ColorMaterial>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum AppState {
+    Start,
+    Restart,
+    MainMenu,
+    InGame,
+    Reset,
+    Replay,
+    GameOver,
+    Hosting,
+    Lobby,
+    Joining,
+    NetClient,
+    Rollback,
+    Setup,
+    Profiles,
+    History,
+    Leaderboard,
+    Tournament,
+    Achievements,
+    Resume
+}
+
+enum MoveDirection {
+    UP,
+    DOWN,
+    LEFT,
+    RIGHT
+}
+
+// TODO: Player paddle and ball colors
+// TODO: Maybe font colors also etc..?  :)
+struct Config {
+    player1_start_position: Vec3,
+    player2_start_position: Vec3,
+    // Top and bottom paddles of the four player mode
+    player3_start_position: Vec3,
+    player4_start_position: Vec3,
+    // Front paddles of the doubles mode
+    left_front_start_position: Vec3,
+    right_front_start_position: Vec3,
+    paddle_size: Vec3,
+    paddle_half_height: f32,
+    window_half_height: f32,
+    window_half_width: f32,
+}
+
+impl FromWorld for Config {
+    fn from_world(world: &mut World) -> Self {
+        let window = world.get_resource::<Windows>().unwrap().get_primary().unwrap();
+        Config::new(window.width(), window.height())
+    }
+}
+
+impl Config {
+    // The headless server and the tests have no window, so they pass the size in
+    fn new(window_width: f32, window_height: f32) -> Self {
+        let p1_start_x = -window_width / 2.0 + 70.0;
+        let p2_start_x = window_width / 2.0 - 70.0;
+        let p3_start_y = window_height / 2.0 - 40.0;
+        let p4_start_y = -window_height / 2.0 + 40.0;
+
+        let paddle_size = Vec3::new(50.0, window_height / 4.0, 10.0);
+        let paddle_half_height = window_height / 8.0;
+        let window_half_height = window_height / 2.0;
+        let window_half_width = window_width / 2.0;
+
+        Config {
+            player1_start_position: Vec3::new(p1_start_x, 0.0, 0.0),
+            player2_start_position: Vec3::new(p2_start_x, 0.0, 0.0),
+            player3_start_position: Vec3::new(0.0, p3_start_y, 0.0),
+            player4_start_position: Vec3::new(0.0, p4_start_y, 0.0),
+            left_front_start_position: Vec3::new(-window_width / 4.0, 0.0, 0.0),
+            right_front_start_position: Vec3::new(window_width / 4.0, 0.0, 0.0),
+            paddle_size,
+            paddle_half_height,
+            window_half_height,
+            window_half_width
+        }
+    }
+}
+
+const WINDOW_WIDTH: f32 = 1280.0;
+const WINDOW_HEIGHT: f32 = 720.0;
+const PADDLE_SPEED: f32 = 10.0;
+const PADDLE_WIDTH: f32 = 50.0;
+const BALL_RADIUS: f32 = 15.0;
+const BALL_SPAWN_SPEED: f32 = 7.0;
+const BALL_ACCEL: f32 = 1.0;
+const STARTING_LIVES: i32 = 3;
+const PADDLE_COLORS: [Color; 6] = [
+    Color::rgb(1.0, 1.0, 1.0),
+    Color::rgb(0.92, 0.39, 0.20),
+    Color::rgb(0.17, 0.78, 0.19),
+    Color::rgb(0.0, 0.62, 1.0),
+    Color::rgb(0.6, 0.2, 0.8),
+    Color::rgb(1.0, 0.85, 0.2),
+];
+const TIME_ATTACK_SECONDS: f32 = 180.0;
+
+pub fn run() {
+    let mut app = App::new();
+
+    // A peer-to-peer rollback match skips the menus entirely
+    let start_state = match rollback::parse_args() {
+        Some(rollback_config) => {
+            app.insert_resource(rollback_config);
+            AppState::Rollback
+        },
+        None => AppState::Start
+    };
+
+    app
+        .insert_resource(WindowDescriptor {
+            title: "Pong!".to_string(),
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            ..Default::default()
+        })
+        .add_plugins(DefaultPlugins)
+        .insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.04)))
+        .insert_resource(ScoreBoard::default())
+        .insert_resource(MatchRules::default())
+        .insert_resource(Players::default())
+        .init_resource::<Config>()
+        .insert_resource(MatchSeed(rand::random()))
+        .add_event::<PaddleHit>()
+        .add_event::<WallBounce>()
+        .add_event::<GoalScored>()
+        .add_event::<RallyEnded>()
+        .add_event::<MatchWon>()
+        .add_event::<RunEnded>()
+        .add_state(start_state)
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(GameOverPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(NetPlugin)
+        .add_plugin(RollbackPlugin)
+        .add_plugin(PowerUpPlugin)
+        .add_plugin(FourPlayerPlugin)
+        .add_plugin(DoublesPlugin)
+        .add_plugin(ArenaPlugin)
+        .add_plugin(BreakoutPlugin)
+        .add_plugin(PracticePlugin)
+        .add_plugin(TimeAttackPlugin)
+        .add_plugin(SetupPlugin)
+        .add_plugin(AiPlugin)
+        .add_plugin(ProfilesPlugin)
+        .add_plugin(HistoryPlugin)
+        .add_plugin(RatingsPlugin)
+        .add_plugin(TournamentPlugin)
+        .add_plugin(AchievementsPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(MatchStatsPlugin)
+        .add_plugin(SavedMatchPlugin)
+        .add_startup_system(setup_cameras)
+        .add_startup_system(setup_ui)
+        .add_startup_system(create_paddles)
+        .add_startup_system(spawn_ball)
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(move_ball.label("move_ball"))
+                .with_system(player1_input)
+                .with_system(player2_input)
+                .with_system(check_collisions)
+                .with_system(scoreboard_system)
+                .with_system(main_menu_controls)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Reset)
+                .with_system(reset_ball)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(reset_ball.label("reset_ball"))
+                .with_system(reset_paddles.label("reset_paddles").after("reset_ball"))
+                .with_system(reset_score.after("reset_paddles"))
+        )
+        .run();
+}
+
+fn setup_cameras(mut commands: Commands) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(UiCameraBundle::default());
+}
+
+// TODO: Maybe do this in a plugin?
+fn setup_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    windows: Res<Windows>
+) {
+    let window = windows.get_primary().unwrap();
+
+    let offset = window.height() / 10.0;
+
+    // Create the 'net'
+    for i in 0..10 {
+        commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(1.0, 1.0, 1.0),
+                ..Default::default()
+            },
+            transform: Transform {
+                scale: Vec3::new(10.0, 20.0, 0.0),
+                translation: Vec3::new(0.0, offset * i as f32 - window.height() / 2.0 + 20.0, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    // Player 1 score
+    commands.spawn_bundle(TextBundle {
+        text: Text {
+            sections: vec![
+                // Player 1 score section
+                TextSection {
+                    value: "".to_string(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 40.0,
+                        color: Color::rgb(1.0, 1.0, 1.0),
+                    },
+                    ..Default::default()
+                }
+            ],
+            ..Default::default()
+        },
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(10.0),
+                left: Val::Px(window.width() / 2.0 - 50.0 - 20.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(Player1ScoreText);
+
+    // Player 2 score
+    commands.spawn_bundle(TextBundle {
+        text: Text {
+            sections: vec![
+                TextSection {
+                    value: "".to_string(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 40.0,
+                        color: Color::rgb(1.0, 1.0, 1.0),
+                    },
+                    ..Default::default()
+                }
+            ],
+            ..Default::default()
+        },
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(10.0),
+                left: Val::Px(window.width() / 2.0 + 50.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(Player2ScoreText);
+}
+
+fn create_paddles(config: Res<Config>, mut commands: Commands) {
+    // first paddle
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: Color::rgb(1.0, 1.0, 1.0),
+            ..Default::default()
+        },
+        transform: Transform {
+            scale: config.paddle_size,
+            translation: config.player1_start_position,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .insert(Paddle { side: Side::Left })
+    .insert(Player1);
+
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: Color::rgb(1.0, 1.0, 1.0),
+            ..Default::default()
+        },
+        transform: Transform {
+            scale: config.paddle_size,
+            translation: config.player2_start_position,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .insert(Paddle { side: Side::Right })
+    .insert(Player2);
+}
+
+fn spawn_ball(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>
+) {
+    let ball_assets = BallAssets {
+        mesh: meshes.add(Mesh::from(shape::UVSphere::default())),
+        material: materials.add(ColorMaterial::from(Color::rgb(1.0, 1.0, 1.0))),
+    };
+
+    add_ball(&mut commands, &ball_assets, Vec3::ZERO, Ball {
+        velocity: physics::ball_start_velocity(&mut rand::thread_rng()),
+        last_paddle: None,
+        speed_effects: Vec::new(),
+    });
+
+    commands.insert_resource(ball_assets);
+}
+
+fn add_ball(commands: &mut Commands, ball_assets: &BallAssets, translation: Vec3, ball: Ball) -> Entity {
+    commands.spawn_bundle(MaterialMesh2dBundle {
+        mesh: ball_assets.mesh.clone().into(),
+        transform: Transform {
+            scale: Vec3::new(BALL_RADIUS, BALL_RADIUS, 0.0),
+            translation,
+            ..Default::default()
+        },
+        material: ball_assets.material.clone(),
+        ..Default::default()
+    })
+    .insert(ball)
+    .id()
+}
+
+fn reset_ball(
+    mut commands: Commands,
+    mut app_state: ResMut<State<AppState>>,
+    rules: Res<MatchRules>,
+    ball_assets: Res<BallAssets>,
+    replay_settings: Res<ReplaySettings>,
+    replay_buffer: Res<ReplayBuffer>,
+    mut seed: ResMut<MatchSeed>,
+    mut ball_query: Query<(Entity, &mut Transform, &mut Ball), Without<Paddle>>
+) {
+    let mut rng = StdRng::seed_from_u64(seed.0);
+    let mut balls = ball_query.iter_mut();
+
+    // Reset ball and randomize starting velocity again
+    if let Some((_, mut ball_transform, mut ball)) = balls.next() {
+        ball_transform.translation = Vec3::new(0.0, 0.0, 0.0);
+        ball.velocity = physics::ball_start_velocity(&mut rng);
+        ball.last_paddle = None;
+        ball.speed_effects.clear();
+    }
+
+    // Extra balls still in play after a restart
+    for (entity, _, _) in balls {
+        commands.entity(entity).despawn();
+    }
+
+    for _ in 1..rules.mode.balls_per_round() {
+        add_ball(&mut commands, &ball_assets, Vec3::ZERO, Ball {
+            velocity: physics::ball_start_velocity(&mut rng),
+            last_paddle: None,
+            speed_effects: Vec::new(),
+        });
+    }
+
+    seed.0 = rng.gen();
+
+    // Show the replay of the last point or go straight to InGame state
+    // to start another round if state was round Reset. A player joining or
+    // leaving a hosted match on the same frame has already queued its own state.
+    if *app_state.current() == AppState::Reset {
+        if replay_settings.enabled && !replay_buffer.is_empty() {
+            let _ = app_state.set(AppState::Replay);
+        } else {
+            let _ = app_state.set(AppState::InGame);
+        }
+    }
+}
+
+fn reset_paddles(
+    config: Res<Config>,
+    mut player1_query: Query<(&mut Transform, &Player1), Without<Player2>>,
+    mut player2_query: Query<(&mut Transform, &Player2), Without<Player1>>
+) {
+    let (mut p1, _) = player1_query.single_mut();
+    let (mut p2, _) = player2_query.single_mut();
+
+    p1.translation = config.player1_start_position;
+    p2.translation = config.player2_start_position;
+}
+
+fn reset_score(
+    rules: Res<MatchRules>,
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut app_state: ResMut<State<AppState>>
+) {
+    let starting_points = rules.handicaps.map(|handicap| handicap.starting_points);
+
+    // A head start for nobody in particular would end a practice run right away
+    if rules.mode == GameMode::Practice {
+        scoreboard.player1 = 0;
+        scoreboard.player2 = 0;
+    } else {
+        scoreboard.player1 = starting_points[Side::Left.index()];
+        scoreboard.player2 = starting_points[Side::Right.index()];
+    }
+
+    scoreboard.lives = starting_points.map(|points| STARTING_LIVES + points);
+    scoreboard.hits = 0;
+    scoreboard.time_left = TIME_ATTACK_SECONDS;
+    scoreboard.rally = 0;
+    scoreboard.longest_rally = 0;
+    scoreboard.fastest_hits = [0.0; 4];
+
+    // Change to InGame, unless a player joining or leaving a hosted match queued a state already
+    let _ = app_state.set(AppState::InGame);
+}
+
+fn move_ball(
+    mut commands: Commands,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut app_state: ResMut<State<AppState>>,
+    mut wall_bounces: EventWriter<WallBounce>,
+    mut goals_scored: EventWriter<GoalScored>,
+    mut rallies_ended: EventWriter<RallyEnded>,
+    mut match_won: EventWriter<MatchWon>,
+    mut runs_ended: EventWriter<RunEnded>,
+    mut ball_query: Query<(Entity, &mut Transform, &mut Ball)>,
+    paddle_query: Query<&Paddle>
+) {
+    let goals = scoreboard.goals(&rules);
+    let mut balls_left = ball_query.iter().count();
+    let mut scored = false;
+
+    // Every ball that leaves the arena is a point, the round goes on until all of them are gone
+    for (entity, mut transform, mut ball) in ball_query.iter_mut() {
+        let velocity = ball.velocity;
+        let goal = physics::step_ball_in_arena(&config, &mut transform.translation, &mut ball.velocity, goals);
+
+        // Walls only ever turn the ball around
+        if ball.velocity.x != velocity.x {
+            wall_bounces.send(WallBounce { side: if velocity.x < 0.0 { Side::Left } else { Side::Right } });
+        }
+
+        if ball.velocity.y != velocity.y {
+            wall_bounces.send(WallBounce { side: if velocity.y < 0.0 { Side::Bottom } else { Side::Top } });
+        }
+
+        if let Some(side) = goal {
+            let scorer = match rules.mode.sides() {
+                [Side::Left, Side::Right] => Some(if side == Side::Left { Side::Right } else { Side::Left }),
+                _ => ball.last_paddle
+                    .and_then(|paddle| paddle_query.get(paddle).ok())
+                    .map(|paddle| paddle.side)
+                    .filter(|scorer| *scorer != side)
+            };
+
+            goals_scored.send(GoalScored { side, scorer });
+            scoreboard.goal(&rules, side);
+
+            scored = true;
+            balls_left -= 1;
+
+            // The last ball is kept around, reset_ball puts it back in the middle
+            if balls_left > 0 {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    // Transition to Reset state when the round is over,
+    // or to GameOver if that was the winning point
+    if scored {
+        let winner = scoreboard.winner(&rules);
+
+        // In multi-ball the rally goes on with the balls still in play
+        if winner.is_some() || balls_left == 0 {
+            rallies_ended.send(RallyEnded { hits: scoreboard.rally });
+            scoreboard.rally = 0;
+        }
+
+        if let Some(winner) = winner {
+            if rules.mode == GameMode::Practice {
+                runs_ended.send(RunEnded { hits: scoreboard.hits });
+            } else {
+                match_won.send(MatchWon { winner: Side::ALL[winner as usize - 1] });
+            }
+
+            // The network may have queued a state on this frame when a player joined or left, that one wins
+            let _ = app_state.set(AppState::GameOver);
+        } else if balls_left == 0 {
+            let _ = app_state.set(AppState::Reset);
+        }
+    }
+}
+
+fn player1_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    mut player1_query: Query<&mut Transform, With<Player1>>
+) {
+    if !players.is_human(Side::Left) {
+        return;
+    }
+
+    let mut transform = player1_query.single_mut();
+    let speed = rules.paddle_speed(Side::Left);
+    let (up, down) = players.0[Side::Left.index()].keys.up_down();
+
+    if keyboard_input.pressed(up) {
+        move_and_cap_paddle(&config, &mut transform, MoveDirection::UP, speed);
+    }
+
+    if keyboard_input.pressed(down) {
+        move_and_cap_paddle(&config, &mut transform, MoveDirection::DOWN, speed);
+    }
+}
+
+fn player2_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    remote_input: Option<Res<RemoteInput>>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    mut player2_query: Query<&mut Transform, With<Player2>>
+) {
+    let mut transform = player2_query.single_mut();
+    let speed = rules.paddle_speed(Side::Right);
+
+    // Player 2 is on the other end of the network when hosting
+    let (up, down) = match remote_input {
+        Some(remote) => (remote.up, remote.down),
+        None if !players.is_human(Side::Right) => return,
+        None => {
+            let (up, down) = players.0[Side::Right.index()].keys.up_down();
+            (keyboard_input.pressed(up), keyboard_input.pressed(down))
+        }
+    };
+
+    if up {
+        move_and_cap_paddle(&config, &mut transform, MoveDirection::UP, speed);
+    }
+    
+    if down {
+        move_and_cap_paddle(&config, &mut transform, MoveDirection::DOWN, speed);
+    }
+}
+
+fn move_and_cap_paddle(
+    config: &Res<Config>, 
+    transform: &mut Mut<Transform>,
+    direction: MoveDirection,
+    speed: f32
+) {
+    // Power-ups change the paddle length, so the scale is used instead of the config
+    match direction {
+        MoveDirection::UP | MoveDirection::DOWN => {
+            let half_length = transform.scale.y / 2.0;
+            transform.translation.y = physics::move_paddle(config, transform.translation.y, half_length, direction, speed);
+        },
+        MoveDirection::LEFT | MoveDirection::RIGHT => {
+            let half_length = transform.scale.x / 2.0;
+            transform.translation.x = physics::move_paddle(config, transform.translation.x, half_length, direction, speed);
+        }
+    }
+}
+
+fn check_collisions(
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut paddle_hits: EventWriter<PaddleHit>,
+    mut ball_query: Query<(&Transform, &mut Ball)>,
+    paddle_query: Query<(Entity, &Paddle, &Transform, Option<&FrontPaddle>), Without<Eliminated>>
+) {
+    for (ball_transform, mut ball) in ball_query.iter_mut() {
+        for (entity, paddle, paddle_transform, front) in paddle_query.iter() {
+            // Front paddles let their own team's shots through from behind
+            let moving_away = match paddle.side {
+                Side::Left => ball.velocity.x > 0.0,
+                Side::Right => ball.velocity.x < 0.0,
+                _ => false
+            };
+
+            if front.is_some() && moving_away {
+                continue;
+            }
+
+            let hit = physics::bounce_off_paddle(
+                ball_transform.translation,
+                &mut ball.velocity,
+                paddle_transform.translation,
+                paddle_transform.scale.truncate() / 2.0
+            );
+
+            if hit {
+                ball.last_paddle = Some(entity);
+                scoreboard.hits += 1;
+                scoreboard.rally += 1;
+                scoreboard.longest_rally = scoreboard.longest_rally.max(scoreboard.rally);
+
+                let speed = ball.velocity.length();
+                let fastest_hit = &mut scoreboard.fastest_hits[paddle.side.index()];
+                *fastest_hit = fastest_hit.max(speed);
+
+                paddle_hits.send(PaddleHit {
+                    paddle: entity,
+                    side: paddle.side,
+                    speed,
+                    zone: paddle_zone(paddle.side, ball_transform.translation, paddle_transform)
+                });
+            }
+        }
+    }
+}
+
+// The zone of the paddle the ball is at, counted along the paddle's length
+fn paddle_zone(side: Side, ball_position: Vec3, paddle_transform: &Transform) -> usize {
+    let (offset, length) = if side.is_horizontal() {
+        (ball_position.x - paddle_transform.translation.x, paddle_transform.scale.x)
+    } else {
+        (ball_position.y - paddle_transform.translation.y, paddle_transform.scale.y)
+    };
+
+    // From 0 at one end to 1 at the other, a ball overlapping the end still counts for the end zone
+    let along = (offset / length + 0.5).clamp(0.0, 1.0);
+
+    ((along * PADDLE_ZONES as f32) as usize).min(PADDLE_ZONES - 1)
+}
+
+// TODO: Horrible with/without, should figure out a better way..
+fn scoreboard_system(
+    scoreboard: Res<ScoreBoard>,
+    rules: Res<MatchRules>,
+    mut player1_text_query: Query<&mut Text, (With<Player1ScoreText>, Without<Player2ScoreText>)>,
+    mut player2_text_query: Query<&mut Text, (With<Player2ScoreText>, Without<Player1ScoreText>)>,
+) {
+    let mut player1_text = player1_text_query.single_mut();
+    let mut player2_text = player2_text_query.single_mut();
+
+    // Four players count down their lives instead, and a practice run only counts hits
+    let (player1, player2) = match rules.mode {
+        GameMode::FourPlayer => (
+            format!("{}", scoreboard.lives[Side::Left.index()]),
+            format!("{}", scoreboard.lives[Side::Right.index()])
+        ),
+        GameMode::Practice => (format!("{}", scoreboard.hits), String::new()),
+        _ => (format!("{}", scoreboard.player1), format!("{}", scoreboard.player2))
+    };
+
+    player1_text.sections[0].value = player1;
+    player2_text.sections[0].value = player2;
+}
+
+// Toggle main menu on/off with Esc key
+fn main_menu_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>
+) {
+    if *app_state.current() == AppState::InGame {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            // A player joining or leaving a hosted match may have changed the state already
+            let _ = app_state.set(AppState::MainMenu);
+            keyboard_input.reset(KeyCode::Escape);
+        }
+    }
+}
\ No newline at end of file
@@ -1,5 +1,9 @@
 use bevy::{prelude::*, app::AppExit};
 use super::AppState;
+use super::net::HostSession;
+use super::replay::ReplaySettings;
+use super::saved_match::{self, SavedMatch};
+use super::tournament::Tournament;
 
 pub struct MainMenuPlugin;
 
@@ -11,8 +15,17 @@ struct MainMenu {
 #[derive(Component)]
 enum MenuButton {
     Play,
+    Resume,
     Continue,
     Restart,
+    Replays,
+    Profiles,
+    History,
+    Leaderboard,
+    Tournament,
+    Achievements,
+    Host,
+    Join,
     Quit
 }
 
@@ -21,6 +34,10 @@ struct MenuColors {
     play_button_hover: Color,
     restart_button_normal: Color,
     restart_button_hover: Color,
+    replays_button_normal: Color,
+    replays_button_hover: Color,
+    net_button_normal: Color,
+    net_button_hover: Color,
     quit_button_normal: Color,
     quit_button_hover: Color,
 }
@@ -33,6 +50,10 @@ impl FromWorld for MenuColors {
             play_button_hover: Color::rgb(0.16, 1.0, 0.18),
             restart_button_normal: Color::rgb(0.0, 0.62, 1.0),
             restart_button_hover: Color::rgb(0.0, 0.38, 1.0),
+            replays_button_normal: Color::rgb(0.55, 0.55, 0.55),
+            replays_button_hover: Color::rgb(0.4, 0.4, 0.4),
+            net_button_normal: Color::rgb(0.6, 0.2, 0.8),
+            net_button_hover: Color::rgb(0.45, 0.0, 0.7),
             quit_button_normal: Color::rgb(1.0, 0.12, 0.11),
             quit_button_hover: Color::rgb(0.84, 0.0, 0.04),
         }
@@ -85,6 +106,18 @@ fn button(color: Color) -> ButtonBundle {
     }
 }
 
+fn button_row() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(410.0), Val::Px(50.0)),
+            justify_content: JustifyContent::SpaceBetween,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    }
+}
+
 fn button_text(text: &str, asset_server: &Res<AssetServer>) -> TextBundle {
     TextBundle{
         text: Text::with_section(text, TextStyle {
@@ -100,7 +133,10 @@ fn setup_menu(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     app_state: Res<State<AppState>>,
-    colors: Res<MenuColors>
+    colors: Res<MenuColors>,
+    replay_settings: Res<ReplaySettings>,
+    host_session: Option<Res<HostSession>>,
+    tournament: Option<Res<Tournament>>
 ) {
     // Stash the id for cleanup
     let ui_camera = commands.spawn_bundle(UiCameraBundle::default()).id();
@@ -120,7 +156,7 @@ fn setup_menu(
         // border node
         parent.spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Percent(50.0), Val::Percent(50.0)),
+                size: Size::new(Val::Percent(50.0), Val::Percent(90.0)),
                 border: Rect::all(Val::Px(20.0)),
                 ..Default::default()
             },
@@ -183,11 +219,25 @@ fn setup_menu(
                     let component = if *app_state.current() == AppState::Start { MenuButton::Play } else { MenuButton::Continue };
                     let text = if *app_state.current() == AppState::Start { "Play" } else { "Continue" };
 
-                    // Play/Continue
-                    parent.spawn_bundle(button(colors.play_button_normal))
-                        .with_children(|parent| {
-                            parent.spawn_bundle(button_text(text, &asset_server));
-                        }).insert(component);
+                    // Play/Continue, and Resume next to Play when a match was saved
+                    if *app_state.current() == AppState::Start && saved_match::exists() {
+                        parent.spawn_bundle(button_row()).with_children(|parent| {
+                            parent.spawn_bundle(button(colors.play_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text(text, &asset_server));
+                                }).insert(component);
+
+                            parent.spawn_bundle(button(colors.play_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("Resume", &asset_server));
+                                }).insert(MenuButton::Resume);
+                        });
+                    } else {
+                        parent.spawn_bundle(button(colors.play_button_normal))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(button_text(text, &asset_server));
+                            }).insert(component);
+                    }
 
                     // Restart
                     parent.spawn_bundle(button(colors.restart_button_normal))
@@ -195,10 +245,65 @@ fn setup_menu(
                             parent.spawn_bundle(button_text("Restart", &asset_server));
                         }).insert(MenuButton::Restart);
 
-                    // Quit
+                    // Replays on/off
+                    parent.spawn_bundle(button(colors.replays_button_normal))
+                        .with_children(|parent| {
+                            parent.spawn_bundle(button_text(replays_text(&replay_settings), &asset_server));
+                        }).insert(MenuButton::Replays);
+
+                    // Profiles, rankings, history, tournaments, achievements and network play, only from the start menu
+                    if *app_state.current() == AppState::Start {
+                        // Side by side to leave room for the rest
+                        parent.spawn_bundle(button_row()).with_children(|parent| {
+                            parent.spawn_bundle(button(colors.replays_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("Profiles", &asset_server));
+                                }).insert(MenuButton::Profiles);
+
+                            parent.spawn_bundle(button(colors.replays_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("Leaderboard", &asset_server));
+                                }).insert(MenuButton::Leaderboard);
+                        });
+
+                        parent.spawn_bundle(button_row()).with_children(|parent| {
+                            parent.spawn_bundle(button(colors.replays_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("History", &asset_server));
+                                }).insert(MenuButton::History);
+
+                            parent.spawn_bundle(button(colors.replays_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("Tournament", &asset_server));
+                                }).insert(MenuButton::Tournament);
+                        });
+
+                        parent.spawn_bundle(button(colors.replays_button_normal))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(button_text("Achievements", &asset_server));
+                            }).insert(MenuButton::Achievements);
+
+                        parent.spawn_bundle(button_row()).with_children(|parent| {
+                            parent.spawn_bundle(button(colors.net_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("Host LAN game", &asset_server));
+                                }).insert(MenuButton::Host);
+
+                            parent.spawn_bundle(button(colors.net_button_normal))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(button_text("Join LAN game", &asset_server));
+                                }).insert(MenuButton::Join);
+                        });
+                    }
+
+                    // Quitting in the middle of a match saves it for later
+                    let saves = *app_state.current() == AppState::MainMenu
+                        && saved_match::can_save(host_session.is_some(), tournament.as_deref());
+                    let text = if saves { "Save & Quit" } else { "Quit" };
+
                     parent.spawn_bundle(button(colors.quit_button_normal))
                         .with_children(|parent| {
-                            parent.spawn_bundle(button_text("Quit", &asset_server));
+                            parent.spawn_bundle(button_text(text, &asset_server));
                         }).insert(MenuButton::Quit);
                 });
             });
@@ -211,34 +316,68 @@ fn setup_menu(
     });
 }
 
+fn replays_text(replay_settings: &ReplaySettings) -> &'static str {
+    if replay_settings.enabled { "Replays: On" } else { "Replays: Off" }
+}
+
 fn button_system(
     mut app_state: ResMut<State<AppState>>,
     colors: Res<MenuColors>,
+    mut replay_settings: ResMut<ReplaySettings>,
     mut interaction_query: Query<
-        (&Interaction, &mut UiColor, &MenuButton),
+        (&Interaction, &mut UiColor, &MenuButton, &Children),
         (Changed<Interaction>, With<Button>)>,
-    mut exit: EventWriter<AppExit>
+    mut text_query: Query<&mut Text>,
+    mut exit: EventWriter<AppExit>,
+    saved_match: Option<Res<SavedMatch>>
 ) {
-    for (interaction, mut color, menu_button) in interaction_query.iter_mut() {
+    for (interaction, mut color, menu_button, children) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Hovered => {
                 match *menu_button {
-                    MenuButton::Play | MenuButton::Continue => *color = colors.play_button_hover.into(),
+                    MenuButton::Play | MenuButton::Resume | MenuButton::Continue => *color = colors.play_button_hover.into(),
                     MenuButton::Restart => *color = colors.restart_button_hover.into(),
+                    MenuButton::Replays | MenuButton::Profiles | MenuButton::History | MenuButton::Leaderboard | MenuButton::Tournament | MenuButton::Achievements => *color = colors.replays_button_hover.into(),
+                    MenuButton::Host | MenuButton::Join => *color = colors.net_button_hover.into(),
                     MenuButton::Quit => *color = colors.quit_button_hover.into(),
                 }
             },
             Interaction::Clicked => {
                 match *menu_button {
-                    MenuButton::Continue | MenuButton::Play => app_state.set(AppState::InGame).unwrap(),
-                    MenuButton::Restart => app_state.set(AppState::Restart).unwrap(),
-                    MenuButton::Quit => exit.send(AppExit),
+                    // The pause menu is open during a hosted match, where a player joining or leaving may have changed the state already
+                    MenuButton::Continue => { let _ = app_state.set(AppState::InGame); },
+                    // Start menu can also be reached after a network match, so start fresh after the setup
+                    MenuButton::Play => app_state.set(AppState::Setup).unwrap(),
+                    MenuButton::Resume => app_state.set(AppState::Resume).unwrap(),
+                    MenuButton::Restart => { let _ = app_state.set(AppState::Restart); },
+                    MenuButton::Replays => {
+                        replay_settings.enabled = !replay_settings.enabled;
+
+                        let mut text = text_query.get_mut(children[0]).unwrap();
+                        text.sections[0].value = replays_text(&replay_settings).to_string();
+                    },
+                    MenuButton::Profiles => app_state.set(AppState::Profiles).unwrap(),
+                    MenuButton::History => app_state.set(AppState::History).unwrap(),
+                    MenuButton::Leaderboard => app_state.set(AppState::Leaderboard).unwrap(),
+                    MenuButton::Tournament => app_state.set(AppState::Tournament).unwrap(),
+                    MenuButton::Achievements => app_state.set(AppState::Achievements).unwrap(),
+                    MenuButton::Host => app_state.set(AppState::Hosting).unwrap(),
+                    MenuButton::Join => app_state.set(AppState::Lobby).unwrap(),
+                    MenuButton::Quit => {
+                        if let Some(saved_match) = &saved_match {
+                            saved_match.save();
+                        }
+
+                        exit.send(AppExit);
+                    },
                 }
             },
             Interaction::None => {
                 match *menu_button {
-                    MenuButton::Continue | MenuButton::Play => *color = colors.play_button_normal.into(),
+                    MenuButton::Continue | MenuButton::Play | MenuButton::Resume => *color = colors.play_button_normal.into(),
                     MenuButton::Restart => *color = colors.restart_button_normal.into(),
+                    MenuButton::Replays | MenuButton::Profiles | MenuButton::History | MenuButton::Leaderboard | MenuButton::Tournament | MenuButton::Achievements => *color = colors.replays_button_normal.into(),
+                    MenuButton::Host | MenuButton::Join => *color = colors.net_button_normal.into(),
                     MenuButton::Quit => *color = colors.quit_button_normal.into(),
                 }
             }
@@ -252,7 +391,8 @@ fn close_menu(
 ) {
     if *app_state.current() == AppState::MainMenu {
         if keyboard_input.just_pressed(KeyCode::Escape) {
-            app_state.set(AppState::InGame).unwrap();
+            // A player joining or leaving a hosted match may have changed the state already
+            let _ = app_state.set(AppState::InGame);
             keyboard_input.reset(KeyCode::Escape);
         }
     }
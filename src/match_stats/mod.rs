@@ -0,0 +1,271 @@
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+use super::{AppState, Ball, GameMode, MatchRules, Players, Side, PADDLE_COLORS};
+use super::events::{GoalScored, PaddleHit, RallyEnded, PADDLE_ZONES};
+use super::ui::{OVERLAY_COLOR, TEXT_COLOR, text_bundle};
+
+pub struct MatchStatsPlugin;
+
+// Numbers for the game over screen, collected from the gameplay events of the match. Indexed by Side.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct MatchStats {
+    points: [u32; 4],
+    serves: [u32; 4],
+    points_on_serve: [u32; 4],
+    hits_per_zone: [[u32; PADDLE_ZONES]; 4],
+    fastest_return: [f32; 4],
+    // Seconds from each side's paddle hits until someone else touched the ball or the rally ended
+    possession: [f32; 4],
+    rallies: u32,
+    rally_hits: u32,
+    longest_rally: i32,
+    // Points won by each side after every point, for the graph
+    timeline: Vec<[u32; 4]>,
+    // The side the ball left from at the start of the round
+    server: Option<Side>,
+    // A new round started and the server isn't known yet
+    serve_pending: bool,
+    // Who touched the ball last in the current rally
+    holder: Option<Side>,
+}
+
+const GRAPH_WIDTH: f32 = 400.0;
+const GRAPH_HEIGHT: f32 = 120.0;
+const GRAPH_DOT: f32 = 6.0;
+const LABEL_WIDTH: f32 = 200.0;
+const COLUMN_WIDTH: f32 = 150.0;
+
+impl Plugin for MatchStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<MatchStats>()
+        // Goals end the round in move_ball, so they're counted before the next round starts
+        .add_system(collect_events.after("move_ball"))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(clear_stats)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Reset)
+                .with_system(next_round)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(track_round)
+        );
+    }
+}
+
+fn clear_stats(mut stats: ResMut<MatchStats>) {
+    *stats = MatchStats { serve_pending: true, ..Default::default() };
+}
+
+fn next_round(mut stats: ResMut<MatchStats>) {
+    stats.serve_pending = true;
+}
+
+// Serves only make sense one on one, the ball starts off towards the receiver
+fn track_round(
+    time: Res<Time>,
+    rules: Res<MatchRules>,
+    ball_query: Query<&Ball>,
+    mut stats: ResMut<MatchStats>
+) {
+    if let Some(holder) = stats.holder {
+        stats.possession[holder.index()] += time.delta_seconds();
+    }
+
+    if !stats.serve_pending {
+        return;
+    }
+
+    stats.serve_pending = false;
+    stats.server = None;
+
+    if rules.mode.sides() != [Side::Left, Side::Right] {
+        return;
+    }
+
+    if let Some(ball) = ball_query.iter().next() {
+        let server = if ball.velocity.x > 0.0 { Side::Left } else { Side::Right };
+        stats.server = Some(server);
+        stats.serves[server.index()] += 1;
+    }
+}
+
+fn collect_events(
+    mut paddle_hits: EventReader<PaddleHit>,
+    mut goals_scored: EventReader<GoalScored>,
+    mut rallies_ended: EventReader<RallyEnded>,
+    mut stats: ResMut<MatchStats>
+) {
+    for hit in paddle_hits.iter() {
+        let side = hit.side.index();
+
+        stats.hits_per_zone[side][hit.zone] += 1;
+        stats.fastest_return[side] = stats.fastest_return[side].max(hit.speed);
+        stats.holder = Some(hit.side);
+    }
+
+    for goal in goals_scored.iter() {
+        if let Some(scorer) = goal.scorer {
+            stats.points[scorer.index()] += 1;
+
+            if stats.server == Some(scorer) {
+                stats.points_on_serve[scorer.index()] += 1;
+            }
+        }
+
+        let points = stats.points;
+        stats.timeline.push(points);
+    }
+
+    for rally in rallies_ended.iter() {
+        stats.rallies += 1;
+        stats.rally_hits += rally.hits as u32;
+        stats.longest_rally = stats.longest_rally.max(rally.hits);
+        stats.holder = None;
+    }
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// A table with a column per player and a graph of the points as they came in
+pub fn spawn_stats(
+    parent: &mut ChildBuilder,
+    stats: &MatchStats,
+    rules: &MatchRules,
+    players: &Players,
+    font: &Handle<Font>
+) {
+    if rules.mode == GameMode::Practice {
+        return;
+    }
+
+    let sides = rules.mode.sides();
+    let colors: Vec<Color> = sides.iter().map(|side| PADDLE_COLORS[players.0[side.index()].color]).collect();
+    let total_possession: f32 = stats.possession.iter().sum();
+
+    let rows: Vec<(&str, Vec<String>)> = vec![
+        ("", sides.iter().map(|side| players.name(rules.mode, *side)).collect()),
+        ("Points won", sides.iter().map(|side| stats.points[side.index()].to_string()).collect()),
+        ("Points won on serve", sides.iter().map(|side| {
+            let side = side.index();
+
+            if stats.serves[side] == 0 {
+                "-".to_string()
+            } else {
+                format!("{} of {} serves", stats.points_on_serve[side], stats.serves[side])
+            }
+        }).collect()),
+        ("Fastest return", sides.iter().map(|side| format!("{:.0}", stats.fastest_return[side.index()])).collect()),
+        ("Time in possession", sides.iter().map(|side| {
+            let seconds = stats.possession[side.index()];
+            let share = if total_possession > 0.0 { seconds / total_possession * 100.0 } else { 0.0 };
+
+            format!("{} ({:.0}%)", format_time(seconds), share)
+        }).collect()),
+        // From one end of the paddle to the other
+        ("Hits per paddle zone", sides.iter().map(|side| {
+            stats.hits_per_zone[side.index()].iter().map(u32::to_string).collect::<Vec<String>>().join(" ")
+        }).collect()),
+    ];
+
+    let average_rally = if stats.rallies > 0 { stats.rally_hits as f32 / stats.rallies as f32 } else { 0.0 };
+
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            padding: Rect::all(Val::Px(10.0)),
+            margin: Rect::all(Val::Px(10.0)),
+            ..Default::default()
+        },
+        color: OVERLAY_COLOR.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for (label, cells) in rows {
+            parent.spawn_bundle(NodeBundle {
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                spawn_cell(parent, label, LABEL_WIDTH, Color::rgb(0.55, 0.55, 0.55), font);
+
+                for (cell, color) in cells.iter().zip(colors.iter()) {
+                    spawn_cell(parent, cell, COLUMN_WIDTH, *color, font);
+                }
+            });
+        }
+
+        parent.spawn_bundle(text_bundle(
+            &format!(
+                "Longest rally {} hits, average {:.1} hits over {} rallies",
+                stats.longest_rally,
+                average_rally,
+                stats.rallies
+            ),
+            16.0,
+            TEXT_COLOR,
+            font
+        ));
+
+        spawn_graph(parent, stats, sides, &colors);
+    });
+}
+
+fn spawn_cell(parent: &mut ChildBuilder, value: &str, width: f32, color: Color, font: &Handle<Font>) {
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(width), Val::Px(24.0)),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle(value, 16.0, color, font));
+    });
+}
+
+// A dot for every side after every point, the points so far going up and the points played going right
+fn spawn_graph(parent: &mut ChildBuilder, stats: &MatchStats, sides: &[Side], colors: &[Color]) {
+    let most_points = stats.timeline.last().map_or(0, |points| *points.iter().max().unwrap()).max(1);
+    let step = GRAPH_WIDTH / stats.timeline.len().max(1) as f32;
+
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(GRAPH_WIDTH + GRAPH_DOT), Val::Px(GRAPH_HEIGHT + GRAPH_DOT)),
+            margin: Rect::all(Val::Px(10.0)),
+            ..Default::default()
+        },
+        color: Color::rgb(0.1, 0.1, 0.1).into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for (index, points) in stats.timeline.iter().enumerate() {
+            for (side, color) in sides.iter().zip(colors.iter()) {
+                let points = points[side.index()];
+
+                parent.spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px((index + 1) as f32 * step - step / 2.0),
+                            bottom: Val::Px(points as f32 / most_points as f32 * GRAPH_HEIGHT),
+                            ..Default::default()
+                        },
+                        size: Size::new(Val::Px(GRAPH_DOT), Val::Px(GRAPH_DOT)),
+                        ..Default::default()
+                    },
+                    color: (*color).into(),
+                    ..Default::default()
+                });
+            }
+        }
+    });
+}
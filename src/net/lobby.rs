@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use bevy::prelude::*;
+use socket2::{Domain, Protocol, Socket, Type};
+use crate::AppState;
+use crate::ui::{self, TEXT_COLOR, text_bundle};
+use super::{NetStatus, connect, receive_all};
+use super::protocol::{Message, Announcement, PROTOCOL_VERSION, DISCOVERY_GROUP, DISCOVERY_PORT};
+
+pub struct LobbyPlugin;
+
+struct LobbyListener {
+    socket: Option<UdpSocket>,
+    // Last time each match was announced, by announcement id
+    last_seen: HashMap<u64, f64>,
+}
+
+// Matches found on the local network and the address they're hosted at,
+// only touched when something that's shown changes
+struct LobbyHosts(Vec<(SocketAddr, Announcement)>);
+
+struct LobbyScreen {
+    ui_root: Entity,
+    host_list: Entity,
+}
+
+impl ui::Screen for LobbyScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+#[derive(Component)]
+enum LobbyButton {
+    Play(SocketAddr),
+    Watch(SocketAddr),
+    EnterAddress,
+    Back
+}
+
+#[derive(Component)]
+struct LobbyStatusText;
+
+// Hosts announce themselves every second, after this they're considered gone
+const HOST_TIMEOUT: f64 = 3.0;
+
+const BUTTON_NORMAL: Color = Color::rgb(0.6, 0.2, 0.8);
+const BUTTON_HOVER: Color = Color::rgb(0.45, 0.0, 0.7);
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(LobbyHosts(Vec::new()))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Lobby)
+                .with_system(start_listening)
+                .with_system(setup_lobby_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Lobby)
+                .with_system(listen_for_hosts)
+                .with_system(update_host_list)
+                .with_system(lobby_buttons)
+                .with_system(lobby_controls)
+                .with_system(status_text)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Lobby)
+                .with_system(ui::cleanup::<LobbyScreen>)
+                .with_system(stop_listening)
+        );
+    }
+}
+
+fn start_listening(
+    mut commands: Commands,
+    mut hosts: ResMut<LobbyHosts>,
+    mut status: ResMut<NetStatus>
+) {
+    let socket = match bind_discovery_socket() {
+        Ok(socket) => {
+            status.0 = "Looking for games on the local network..".to_string();
+            Some(socket)
+        },
+        Err(err) => {
+            status.0 = format!("Can't look for games: {}", err);
+            None
+        }
+    };
+
+    hosts.0.clear();
+    commands.insert_resource(LobbyListener {
+        socket,
+        last_seen: HashMap::new(),
+    });
+}
+
+// Every game on the machine listens on the same port, so a second one can open the lobby too.
+// A unicast would only reach one of them, announcements to the group reach all of them.
+fn bind_discovery_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from(([0, 0, 0, 0], DISCOVERY_PORT)).into())?;
+    socket.join_multicast_v4(&DISCOVERY_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+fn listen_for_hosts(
+    time: Res<Time>,
+    mut listener: ResMut<LobbyListener>,
+    mut hosts: ResMut<LobbyHosts>
+) {
+    let now = time.seconds_since_startup();
+    let messages = match listener.socket {
+        Some(ref socket) => receive_all(socket),
+        None => Vec::new()
+    };
+    let mut found = hosts.0.clone();
+
+    for (message, addr) in messages {
+        let announcement = match message {
+            Message::Announce(announcement) if announcement.version == PROTOCOL_VERSION => announcement,
+            _ => continue
+        };
+
+        listener.last_seen.insert(announcement.id, now);
+
+        match found.iter_mut().find(|(_, known)| known.id == announcement.id) {
+            Some((_, known)) => *known = announcement,
+            None => found.push((SocketAddr::new(addr.ip(), announcement.port), announcement)),
+        }
+    }
+
+    found.retain(|(_, announcement)| {
+        listener.last_seen.get(&announcement.id).is_some_and(|seen| now - seen <= HOST_TIMEOUT)
+    });
+
+    if found != hosts.0 {
+        hosts.0 = found;
+    }
+}
+
+fn setup_lobby_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let mut host_list = None;
+
+    let ui_root = commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("LAN games", 50.0, TEXT_COLOR, &font));
+
+        host_list = Some(parent.spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                margin: Rect::all(Val::Px(20.0)),
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        }).id());
+
+        parent.spawn_bundle(text_bundle("", 20.0, TEXT_COLOR, &font)).insert(LobbyStatusText);
+
+        parent.spawn_bundle(NodeBundle {
+            style: Style {
+                margin: Rect::all(Val::Px(20.0)),
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            spawn_button(parent, "Enter address", LobbyButton::EnterAddress, &font);
+            spawn_button(parent, "Back", LobbyButton::Back, &font);
+        });
+    }).id();
+
+    commands.insert_resource(LobbyScreen {
+        ui_root,
+        host_list: host_list.unwrap(),
+    });
+}
+
+// Rebuild the list of found matches whenever it changes
+fn update_host_list(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    hosts: Res<LobbyHosts>,
+    screen: Res<LobbyScreen>,
+    children_query: Query<&Children>
+) {
+    if !hosts.is_changed() {
+        return;
+    }
+
+    if let Ok(children) = children_query.get(screen.host_list) {
+        for child in children.iter() {
+            commands.entity(*child).despawn_recursive();
+        }
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands.entity(screen.host_list).with_children(|parent| {
+        if hosts.0.is_empty() {
+            parent.spawn_bundle(text_bundle("No games found yet", 20.0, TEXT_COLOR, &font));
+        }
+
+        for (addr, announcement) in hosts.0.iter() {
+            let open = announcement.players.len() < 2;
+            // Dedicated servers announce themselves before anyone has joined
+            let players = if announcement.players.is_empty() {
+                "Waiting for players".to_string()
+            } else {
+                announcement.players.join(" vs ")
+            };
+            let description = format!(
+                "{} - first to {} - {} spectator(s)",
+                players,
+                announcement.points_to_win,
+                announcement.spectators
+            );
+
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(text_bundle(&description, 20.0, TEXT_COLOR, &font));
+
+                if open {
+                    spawn_button(parent, "Play", LobbyButton::Play(*addr), &font);
+                }
+
+                spawn_button(parent, "Watch", LobbyButton::Watch(*addr), &font);
+            });
+        }
+    });
+}
+
+fn lobby_buttons(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut app_state: ResMut<State<AppState>>,
+    mut status: ResMut<NetStatus>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &LobbyButton),
+        (Changed<Interaction>, With<Button>)>
+) {
+    for (interaction, mut color, lobby_button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Hovered => *color = BUTTON_HOVER.into(),
+            Interaction::None => *color = BUTTON_NORMAL.into(),
+            Interaction::Clicked => {
+                match *lobby_button {
+                    LobbyButton::Play(addr) => connect(&mut commands, &time, &mut status, addr, false),
+                    LobbyButton::Watch(addr) => connect(&mut commands, &time, &mut status, addr, true),
+                    LobbyButton::EnterAddress => app_state.set(AppState::Joining).unwrap(),
+                    LobbyButton::Back => app_state.set(AppState::Start).unwrap(),
+                }
+            }
+        }
+    }
+}
+
+fn lobby_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::Start).unwrap();
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+fn status_text(status: Res<NetStatus>, mut text_query: Query<&mut Text, With<LobbyStatusText>>) {
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != status.0 {
+            text.sections[0].value = status.0.clone();
+        }
+    }
+}
+
+fn stop_listening(mut commands: Commands) {
+    commands.remove_resource::<LobbyListener>();
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, lobby_button: LobbyButton, font: &Handle<Font>) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(150.0), Val::Px(40.0)),
+            margin: Rect::all(Val::Px(5.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: BUTTON_NORMAL.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle(text, 20.0, TEXT_COLOR, font));
+    })
+    .insert(lobby_button);
+}
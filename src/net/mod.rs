@@ -0,0 +1,733 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use bevy::prelude::*;
+use super::ui;
+use super::{AppState, Ball, BallAssets, GameMode, MatchRules, Paddle, Player1, Player2, ScoreBoard, add_ball, scoreboard_system};
+
+mod lobby;
+pub mod protocol;
+mod screens;
+use protocol::{Message, Snapshot, Announcement, PROTOCOL_VERSION, DEFAULT_PORT, DISCOVERY_GROUP, DISCOVERY_PORT, MAX_PACKET_SIZE};
+
+pub struct NetPlugin;
+
+// Present while this instance is hosting a network match
+pub struct HostSession {
+    id: u64,
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    peer_name: String,
+    last_received: f64,
+    last_announce: f64,
+    spectators: Vec<Spectator>,
+    tick: u32,
+}
+
+struct Spectator {
+    addr: SocketAddr,
+    last_received: f64,
+}
+
+// Present while this instance is joining or playing in a network match
+pub struct ClientSession {
+    socket: UdpSocket,
+    host: SocketAddr,
+    connected: bool,
+    spectator: bool,
+    started: f64,
+    last_received: f64,
+    last_hello: f64,
+    last_keep_alive: f64,
+    input_seq: u32,
+    latest_tick: u32,
+    // Snapshots with their local receive times, oldest first
+    snapshots: VecDeque<(f64, Snapshot)>,
+}
+
+// Paddle keys held by the remote player, replaces the local Player2 controls while hosting
+pub struct RemoteInput {
+    pub up: bool,
+    pub down: bool,
+    seq: u32,
+}
+
+// The rules picked on the setup screen while a network session plays by two player rules,
+// put back when the session ends
+struct ChosenRules(MatchRules);
+
+// Shown on the host and join screens
+struct NetStatus(String);
+
+// Address typed on the join screen
+struct JoinAddress(String);
+
+// Amount of spectators in the current network match, shown in the HUD
+struct Spectators(u32);
+
+#[derive(Component)]
+struct SpectatorText;
+
+const CONNECTION_TIMEOUT: f64 = 5.0;
+const HELLO_INTERVAL: f64 = 0.5;
+const KEEP_ALIVE_INTERVAL: f64 = 1.0;
+const ANNOUNCE_INTERVAL: f64 = 1.0;
+// How far in the past the client renders, so there's always a snapshot to interpolate towards
+const INTERPOLATION_DELAY: f64 = 0.1;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(NetStatus(String::new()))
+        .insert_resource(JoinAddress("127.0.0.1".to_string()))
+        .insert_resource(Spectators(0))
+        .add_startup_system(setup_spectator_text)
+        .add_system(spectator_text)
+        .add_system(host_receive.label("host_receive"))
+        .add_system(host_send_snapshot.after("host_receive"))
+        .add_system(host_announce.after("host_receive"))
+        .add_system(client_receive.label("client_receive"))
+        .add_plugin(lobby::LobbyPlugin)
+        .add_system_set(
+            SystemSet::on_enter(AppState::Start)
+                .with_system(end_sessions)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Hosting)
+                .with_system(start_hosting)
+                .with_system(two_player_rules)
+                .with_system(screens::setup_hosting_screen)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Lobby)
+                .with_system(two_player_rules)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Hosting)
+                .with_system(hosting_controls)
+                .with_system(screens::update_texts)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Hosting)
+                .with_system(ui::cleanup::<screens::NetScreen>)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Joining)
+                .with_system(start_joining)
+                .with_system(screens::setup_join_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Joining)
+                .with_system(join_controls)
+                .with_system(screens::update_texts)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Joining)
+                .with_system(ui::cleanup::<screens::NetScreen>)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::NetClient)
+                .with_system(client_send_input)
+                .with_system(client_interpolate.after("client_receive"))
+                .with_system(scoreboard_system)
+                .with_system(client_controls)
+        );
+    }
+}
+
+fn start_hosting(
+    mut commands: Commands,
+    time: Res<Time>,
+    session: Option<Res<HostSession>>,
+    mut status: ResMut<NetStatus>
+) {
+    // Already hosting, the previous player just left
+    if session.is_some() {
+        status.0 = "Player left, waiting for another one..".to_string();
+        return;
+    }
+
+    let socket = bind_socket(SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT)))
+        .and_then(|socket| socket.set_multicast_loop_v4(true).map(|_| socket));
+
+    match socket {
+        Ok(socket) => {
+            let now = time.seconds_since_startup();
+
+            status.0 = format!("Waiting for a player on port {}..", DEFAULT_PORT);
+            commands.insert_resource(HostSession {
+                id: rand::random(),
+                socket,
+                peer: None,
+                peer_name: String::new(),
+                last_received: now,
+                last_announce: now - ANNOUNCE_INTERVAL,
+                spectators: Vec::new(),
+                tick: 0,
+            });
+        },
+        Err(err) => status.0 = format!("Could not host: {}", err),
+    }
+}
+
+// Snapshots only carry the balls and two paddles, so network matches can't use
+// the four player, doubles or breakout modes or arenas with obstacles
+fn two_player_rules(
+    mut commands: Commands,
+    mut rules: ResMut<MatchRules>,
+    chosen_rules: Option<Res<ChosenRules>>
+) {
+    // Hosting starts over when a player leaves, the rules are already changed then
+    if chosen_rules.is_none() {
+        commands.insert_resource(ChosenRules(rules.clone()));
+    }
+
+    if matches!(rules.mode, GameMode::FourPlayer | GameMode::Doubles | GameMode::Breakout | GameMode::Practice | GameMode::TimeAttack) {
+        rules.mode = GameMode::Classic;
+    }
+
+    rules.level = 0;
+    // The other end only sees the positions, paddles of any other size would look wrong there
+    rules.handicaps = Default::default();
+}
+
+fn hosting_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        // host_receive starts the match when a player joins on the same frame, that one wins
+        let _ = app_state.set(AppState::Start);
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+// Network matches end whenever the start menu is shown
+fn end_sessions(
+    mut commands: Commands,
+    mut rules: ResMut<MatchRules>,
+    chosen_rules: Option<Res<ChosenRules>>,
+    mut spectators: ResMut<Spectators>,
+    host_session: Option<Res<HostSession>>,
+    client_session: Option<Res<ClientSession>>
+) {
+    // Let anyone still connected know the match is over
+    if let Some(session) = host_session {
+        for addr in session.peer.iter().chain(session.spectators.iter().map(|spectator| &spectator.addr)) {
+            send(&session.socket, *addr, &Message::Disconnect);
+        }
+    }
+
+    if let Some(session) = client_session {
+        send(&session.socket, session.host, &Message::Disconnect);
+    }
+
+    if let Some(chosen_rules) = chosen_rules {
+        *rules = chosen_rules.0.clone();
+        commands.remove_resource::<ChosenRules>();
+    }
+
+    spectators.0 = 0;
+    commands.remove_resource::<HostSession>();
+    commands.remove_resource::<RemoteInput>();
+    commands.remove_resource::<ClientSession>();
+}
+
+fn host_receive(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut app_state: ResMut<State<AppState>>,
+    session: Option<ResMut<HostSession>>,
+    mut remote_input: Option<ResMut<RemoteInput>>,
+    mut spectators: ResMut<Spectators>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+    let now = time.seconds_since_startup();
+    let mut peer_left = false;
+
+    for (message, addr) in receive_all(&session.socket) {
+        match message {
+            Message::Hello { version, name } if version == PROTOCOL_VERSION => {
+                if session.peer.is_none() {
+                    info!("{} joined from {}", name, addr);
+                    session.peer = Some(addr);
+                    session.peer_name = name;
+                    commands.insert_resource(RemoteInput { up: false, down: false, seq: 0 });
+
+                    // Start a fresh match for the new player
+                    let _ = app_state.overwrite_set(AppState::Restart);
+                }
+
+                // Hellos keep coming until our Welcome gets through
+                if session.peer == Some(addr) {
+                    session.last_received = now;
+                    send(&session.socket, addr, &Message::Welcome { spectator: false });
+                } else {
+                    send(&session.socket, addr, &Message::Full);
+                }
+            },
+            Message::Spectate { version } if version == PROTOCOL_VERSION => {
+                match session.spectators.iter_mut().find(|spectator| spectator.addr == addr) {
+                    Some(spectator) => spectator.last_received = now,
+                    None => {
+                        info!("Spectator joined from {}", addr);
+                        session.spectators.push(Spectator { addr, last_received: now });
+                    }
+                }
+
+                send(&session.socket, addr, &Message::Welcome { spectator: true });
+            },
+            Message::KeepAlive => {
+                if let Some(spectator) = session.spectators.iter_mut().find(|spectator| spectator.addr == addr) {
+                    spectator.last_received = now;
+                }
+            },
+            Message::Input { seq, up, down } if session.peer == Some(addr) => {
+                session.last_received = now;
+
+                if let Some(ref mut remote) = remote_input {
+                    if seq > remote.seq {
+                        remote.seq = seq;
+                        remote.up = up;
+                        remote.down = down;
+                    }
+                }
+            },
+            Message::Disconnect if session.peer == Some(addr) => peer_left = true,
+            Message::Disconnect => session.spectators.retain(|spectator| spectator.addr != addr),
+            _ => {}
+        }
+    }
+
+    session.spectators.retain(|spectator| now - spectator.last_received <= CONNECTION_TIMEOUT);
+    spectators.0 = session.spectators.len() as u32;
+
+    if session.peer.is_some() && now - session.last_received > CONNECTION_TIMEOUT {
+        warn!("Player timed out");
+        peer_left = true;
+    }
+
+    if peer_left {
+        session.peer = None;
+        commands.remove_resource::<RemoteInput>();
+        let _ = app_state.overwrite_set(AppState::Hosting);
+    }
+}
+
+fn host_send_snapshot(
+    scoreboard: Res<ScoreBoard>,
+    session: Option<ResMut<HostSession>>,
+    ball_query: Query<&Transform, With<Ball>>,
+    player1_query: Query<&Transform, With<Player1>>,
+    player2_query: Query<&Transform, With<Player2>>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+    // Spectators keep getting snapshots while waiting for a new player
+    if session.peer.is_none() && session.spectators.is_empty() {
+        return;
+    }
+
+    session.tick += 1;
+
+    let snapshot = Snapshot {
+        tick: session.tick,
+        balls: ball_query.iter().map(|t| [t.translation.x, t.translation.y]).collect(),
+        player1: player1_query.single().translation.y,
+        player2: player2_query.single().translation.y,
+        score: (scoreboard.player1, scoreboard.player2),
+        spectators: session.spectators.len() as u32,
+    };
+    let message = Message::Snapshot(snapshot);
+
+    if let Some(peer) = session.peer {
+        send(&session.socket, peer, &message);
+    }
+
+    for spectator in session.spectators.iter() {
+        send(&session.socket, spectator.addr, &message);
+    }
+}
+
+fn host_announce(
+    time: Res<Time>,
+    rules: Res<MatchRules>,
+    session: Option<ResMut<HostSession>>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+    let now = time.seconds_since_startup();
+
+    if now - session.last_announce < ANNOUNCE_INTERVAL {
+        return;
+    }
+
+    session.last_announce = now;
+
+    let mut players = vec![local_player_name()];
+
+    if session.peer.is_some() {
+        players.push(session.peer_name.clone());
+    }
+
+    let message = Message::Announce(Announcement {
+        version: PROTOCOL_VERSION,
+        id: session.id,
+        port: DEFAULT_PORT,
+        players,
+        points_to_win: rules.points_to_win,
+        spectators: session.spectators.len() as u32,
+    });
+
+    // Looped back to lobbies on this machine too
+    send(&session.socket, SocketAddr::from((DISCOVERY_GROUP, DISCOVERY_PORT)), &message);
+}
+
+fn start_joining(mut status: ResMut<NetStatus>) {
+    status.0 = "Type the host address, Enter to play or Tab to spectate".to_string();
+}
+
+fn join_controls(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut received_characters: EventReader<ReceivedCharacter>,
+    mut app_state: ResMut<State<AppState>>,
+    mut address: ResMut<JoinAddress>,
+    mut status: ResMut<NetStatus>
+) {
+    for event in received_characters.iter() {
+        if event.char.is_ascii_alphanumeric() || event.char == '.' || event.char == ':' {
+            address.0.push(event.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        address.0.pop();
+    }
+
+    let spectate = keyboard_input.just_pressed(KeyCode::Tab);
+
+    if keyboard_input.just_pressed(KeyCode::Return) || spectate {
+        match resolve_address(&address.0) {
+            Some(host) => connect(&mut commands, &time, &mut status, host, spectate),
+            None => status.0 = format!("Unknown address '{}'", address.0),
+        }
+    }
+
+    // Back to the lobby
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<ClientSession>();
+        app_state.set(AppState::Lobby).unwrap();
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+fn connect(
+    commands: &mut Commands,
+    time: &Time,
+    status: &mut NetStatus,
+    host: SocketAddr,
+    spectate: bool
+) {
+    match bind_socket(SocketAddr::from(([0, 0, 0, 0], 0))) {
+        Ok(socket) => {
+            let now = time.seconds_since_startup();
+
+            status.0 = format!("Connecting to {}..", host);
+            commands.insert_resource(ClientSession {
+                socket,
+                host,
+                connected: false,
+                spectator: spectate,
+                started: now,
+                last_received: now,
+                last_hello: now - HELLO_INTERVAL,
+                last_keep_alive: now,
+                input_seq: 0,
+                latest_tick: 0,
+                snapshots: VecDeque::new(),
+            });
+        },
+        Err(err) => status.0 = format!("Could not connect: {}", err),
+    }
+}
+
+fn client_receive(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut app_state: ResMut<State<AppState>>,
+    session: Option<ResMut<ClientSession>>,
+    mut status: ResMut<NetStatus>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+    let now = time.seconds_since_startup();
+
+    if !session.connected {
+        if now - session.started > CONNECTION_TIMEOUT {
+            status.0 = format!("No answer from {}", session.host);
+            commands.remove_resource::<ClientSession>();
+            return;
+        }
+
+        if now - session.last_hello >= HELLO_INTERVAL {
+            let hello = if session.spectator {
+                Message::Spectate { version: PROTOCOL_VERSION }
+            } else {
+                Message::Hello { version: PROTOCOL_VERSION, name: local_player_name() }
+            };
+
+            session.last_hello = now;
+            send(&session.socket, session.host, &hello);
+        }
+    }
+
+    let mut host_left = false;
+
+    for (message, addr) in receive_all(&session.socket) {
+        if addr != session.host {
+            continue;
+        }
+
+        session.last_received = now;
+
+        match message {
+            Message::Welcome { spectator } if !session.connected => {
+                info!("Connected to {}{}", session.host, if spectator { " as a spectator" } else { "" });
+                session.connected = true;
+                let _ = app_state.overwrite_set(AppState::NetClient);
+            },
+            Message::Full if !session.connected => {
+                status.0 = "Someone is already playing, press Tab to spectate".to_string();
+                commands.remove_resource::<ClientSession>();
+                return;
+            },
+            // Packets may arrive out of order, older snapshots are useless
+            Message::Snapshot(snapshot) if snapshot.tick > session.latest_tick => {
+                session.latest_tick = snapshot.tick;
+                session.snapshots.push_back((now, snapshot));
+            },
+            Message::Disconnect => host_left = true,
+            _ => {}
+        }
+    }
+
+    if session.connected && now - session.last_received > CONNECTION_TIMEOUT {
+        warn!("Host timed out");
+        host_left = true;
+    }
+
+    if host_left {
+        let _ = app_state.overwrite_set(AppState::Start);
+    }
+}
+
+fn client_send_input(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    session: Option<ResMut<ClientSession>>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+
+    if session.spectator {
+        let now = time.seconds_since_startup();
+
+        if now - session.last_keep_alive >= KEEP_ALIVE_INTERVAL {
+            session.last_keep_alive = now;
+            send(&session.socket, session.host, &Message::KeepAlive);
+        }
+
+        return;
+    }
+
+    session.input_seq += 1;
+
+    // Either set of controls works, there's only one player on this keyboard
+    let message = Message::Input {
+        seq: session.input_seq,
+        up: keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::W),
+        down: keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::S),
+    };
+
+    send(&session.socket, session.host, &message);
+}
+
+fn client_interpolate(
+    mut commands: Commands,
+    time: Res<Time>,
+    ball_assets: Res<BallAssets>,
+    session: Option<ResMut<ClientSession>>,
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut spectators: ResMut<Spectators>,
+    mut ball_query: Query<(Entity, &mut Transform), (With<Ball>, Without<Paddle>)>,
+    mut player1_query: Query<&mut Transform, (With<Paddle>, With<Player1>, Without<Player2>)>,
+    mut player2_query: Query<&mut Transform, (With<Paddle>, With<Player2>, Without<Player1>)>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+    let render_time = time.seconds_since_startup() - INTERPOLATION_DELAY;
+
+    // Keep a single snapshot older than the render time to interpolate from
+    while session.snapshots.len() > 2 && session.snapshots[1].0 <= render_time {
+        session.snapshots.pop_front();
+    }
+
+    if session.snapshots.is_empty() {
+        return;
+    }
+
+    let from = &session.snapshots[0];
+    let to = session.snapshots.get(1).unwrap_or(from);
+    let t = if to.0 > from.0 {
+        ((render_time - from.0) / (to.0 - from.0)).clamp(0.0, 1.0) as f32
+    } else {
+        1.0
+    };
+
+    // Multi-ball matches change the amount of balls during a round
+    let ball_count = ball_query.iter().count();
+
+    for ball in to.1.balls.iter().skip(ball_count) {
+        add_ball(&mut commands, &ball_assets, Vec3::new(ball[0], ball[1], 0.0), Ball {
+            velocity: Vec2::ZERO,
+            last_paddle: None,
+            speed_effects: Vec::new(),
+        });
+    }
+
+    for (entity, _) in ball_query.iter().skip(to.1.balls.len()) {
+        commands.entity(entity).despawn();
+    }
+
+    for ((_, mut transform), (a, b)) in ball_query.iter_mut().zip(from.1.balls.iter().zip(to.1.balls.iter())) {
+        transform.translation.x = lerp(a[0], b[0], t);
+        transform.translation.y = lerp(a[1], b[1], t);
+    }
+
+    player1_query.single_mut().translation.y = lerp(from.1.player1, to.1.player1, t);
+    player2_query.single_mut().translation.y = lerp(from.1.player2, to.1.player2, t);
+
+    scoreboard.player1 = to.1.score.0;
+    scoreboard.player2 = to.1.score.1;
+    spectators.0 = to.1.spectators;
+}
+
+fn client_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        // client_receive goes back to Start by itself when the host leaves on the same frame
+        let _ = app_state.set(AppState::Start);
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+fn setup_spectator_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 20.0,
+                color: Color::rgb(1.0, 1.0, 1.0),
+            },
+            Default::default()
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                bottom: Val::Px(10.0),
+                right: Val::Px(20.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(SpectatorText);
+}
+
+fn spectator_text(spectators: Res<Spectators>, mut text_query: Query<&mut Text, With<SpectatorText>>) {
+    if !spectators.is_changed() {
+        return;
+    }
+
+    let mut text = text_query.single_mut();
+
+    text.sections[0].value = match spectators.0 {
+        0 => String::new(),
+        1 => "1 spectator".to_string(),
+        count => format!("{} spectators", count),
+    };
+}
+
+// Shown to the other player and in lobbies, the name of the logged in user will do
+fn local_player_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string())
+}
+
+pub fn bind_socket(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(addr)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+// Accepts "host" or "host:port", the default port is used if none is given
+fn resolve_address(address: &str) -> Option<SocketAddr> {
+    let address = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+
+    address.to_socket_addrs().ok()?.find(|addr| addr.is_ipv4())
+}
+
+pub fn receive_all(socket: &UdpSocket) -> Vec<(Message, SocketAddr)> {
+    let mut messages = Vec::new();
+    let mut buf = [0; MAX_PACKET_SIZE];
+
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, addr)) => {
+                if let Some(message) = Message::decode(&buf[..len]) {
+                    messages.push((message, addr));
+                }
+            },
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => {
+                warn!("Receiving failed: {}", err);
+                break;
+            }
+        }
+    }
+
+    messages
+}
+
+pub fn send(socket: &UdpSocket, addr: SocketAddr, message: &Message) {
+    if let Err(err) = socket.send_to(&message.encode(), addr) {
+        warn!("Sending to {} failed: {}", addr, err);
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
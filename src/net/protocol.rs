@@ -0,0 +1,66 @@
+use std::net::Ipv4Addr;
+use serde::{Serialize, Deserialize};
+
+pub const PROTOCOL_VERSION: u32 = 3;
+pub const DEFAULT_PORT: u16 = 7777;
+// Hosts announce their matches to this multicast group and port for the lobby screen.
+// Every lobby that joined the group gets a copy, including the ones on the host's own machine.
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 78);
+pub const DISCOVERY_PORT: u16 = 7778;
+pub const MAX_PACKET_SIZE: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Message {
+    // Client -> host, sent until the host answers with Welcome
+    Hello { version: u32, name: String },
+    // Client -> host, sent until the host answers with Welcome to watch the match
+    Spectate { version: u32 },
+    // Client -> host, held paddle keys of the remote player
+    Input { seq: u32, up: bool, down: bool },
+    // Spectator -> host, spectators don't send input so they need to say they're still there
+    KeepAlive,
+    // Host -> client
+    Welcome { spectator: bool },
+    // Host -> client, there's already a player
+    Full,
+    Snapshot(Snapshot),
+    // Host -> broadcast, advertises the match to lobby screens on the local network
+    Announce(Announcement),
+    // Either way, the other end is going away
+    Disconnect,
+}
+
+// Everything the client needs to draw a frame, the host is authoritative.
+// Snapshots always carry the full state, so a spectator joining mid-match
+// is up to date as soon as the first one arrives.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub balls: Vec<[f32; 2]>,
+    pub player1: f32,
+    pub player2: f32,
+    pub score: (i32, i32),
+    pub spectators: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub version: u32,
+    // Random for every hosted match, the same match may be heard from several addresses
+    pub id: u64,
+    // The port the match is hosted on, the address is where the announcement came from
+    pub port: u16,
+    pub players: Vec<String>,
+    pub points_to_win: i32,
+    pub spectators: u32,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Message> {
+        bincode::deserialize(bytes).ok()
+    }
+}
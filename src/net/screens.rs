@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use crate::ui;
+use super::{NetStatus, JoinAddress};
+
+// Root node of the host/join screen, stashed for cleanup
+pub(super) struct NetScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for NetScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+#[derive(Component)]
+struct StatusText;
+
+#[derive(Component)]
+struct AddressText;
+
+pub(super) fn setup_hosting_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let ui_root = spawn_screen(&mut commands, &asset_server, "Host game", false);
+    commands.insert_resource(NetScreen { ui_root });
+}
+
+pub(super) fn setup_join_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let ui_root = spawn_screen(&mut commands, &asset_server, "Join game", true);
+    commands.insert_resource(NetScreen { ui_root });
+}
+
+fn spawn_screen(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    title: &str,
+    with_address: bool
+) -> Entity {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text = |value: &str, font_size: f32| TextBundle {
+        style: Style {
+            margin: Rect::all(Val::Px(10.0)),
+            ..Default::default()
+        },
+        text: Text::with_section(value, TextStyle {
+            font: font.clone(),
+            font_size,
+            color: Color::rgb(1.0, 1.0, 1.0),
+        }, Default::default()),
+        ..Default::default()
+    };
+
+    commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text(title, 50.0));
+
+        if with_address {
+            parent.spawn_bundle(text("", 40.0)).insert(AddressText);
+        }
+
+        parent.spawn_bundle(text("", 20.0)).insert(StatusText);
+        parent.spawn_bundle(text("Esc to go back", 20.0));
+    }).id()
+}
+
+pub(super) fn update_texts(
+    status: Res<NetStatus>,
+    address: Res<JoinAddress>,
+    mut status_query: Query<&mut Text, (With<StatusText>, Without<AddressText>)>,
+    mut address_query: Query<&mut Text, (With<AddressText>, Without<StatusText>)>
+) {
+    for mut text in status_query.iter_mut() {
+        if text.sections[0].value != status.0 {
+            text.sections[0].value = status.0.clone();
+        }
+    }
+
+    for mut text in address_query.iter_mut() {
+        if text.sections[0].value != address.0 {
+            text.sections[0].value = address.0.clone();
+        }
+    }
+}
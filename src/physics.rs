@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use super::{Config, MoveDirection, BALL_ACCEL, BALL_RADIUS, BALL_SPAWN_SPEED, PADDLE_WIDTH};
+
+// Plain game rules without any ECS, so the same rules can be used by the
+// systems in main.rs and by the fixed-step rollback simulation
+
+// The player who got the point when the ball left the arena
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    Player1,
+    Player2
+}
+
+// Edges of the arena, each one is either a wall or a goal someone defends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    // Paddles defending the top and bottom lie flat and move sideways
+    pub fn is_horizontal(self) -> bool {
+        matches!(self, Side::Top | Side::Bottom)
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// Classic pong, the ball bounces off the ceiling and floor
+pub const CLASSIC_GOALS: [bool; 4] = [true, true, false, false];
+
+// Move the ball one tick, reflect it off the ceiling and floor and
+// check whether it left either side of the arena
+pub fn step_ball(config: &Config, position: &mut Vec3, velocity: &mut Vec2) -> Option<Goal> {
+    match step_ball_in_arena(config, position, velocity, CLASSIC_GOALS)? {
+        Side::Left => Some(Goal::Player2),
+        Side::Right => Some(Goal::Player1),
+        _ => None
+    }
+}
+
+// Move the ball one tick, reflect it off every side that isn't a goal and
+// check whether it left the arena through one that is. `goals` is indexed by Side.
+pub fn step_ball_in_arena(config: &Config, position: &mut Vec3, velocity: &mut Vec2, goals: [bool; 4]) -> Option<Side> {
+    // TODO: Add timestep
+    position.x += velocity.x;
+    position.y += velocity.y;
+
+    // check ball collision with the walls and send it back in
+    if !goals[Side::Top.index()] && position.y + BALL_RADIUS >= config.window_half_height {
+        velocity.y = -velocity.y.abs();
+    }
+
+    if !goals[Side::Bottom.index()] && position.y - BALL_RADIUS <= -config.window_half_height {
+        velocity.y = velocity.y.abs();
+    }
+
+    if !goals[Side::Left.index()] && position.x - BALL_RADIUS <= -config.window_half_width {
+        velocity.x = velocity.x.abs();
+    }
+
+    if !goals[Side::Right.index()] && position.x + BALL_RADIUS >= config.window_half_width {
+        velocity.x = -velocity.x.abs();
+    }
+
+    // Check ball collision with the goals
+    if goals[Side::Left.index()] && position.x - BALL_RADIUS < -config.window_half_width {
+        Some(Side::Left)
+    } else if goals[Side::Right.index()] && position.x + BALL_RADIUS > config.window_half_width {
+        Some(Side::Right)
+    } else if goals[Side::Top.index()] && position.y + BALL_RADIUS > config.window_half_height {
+        Some(Side::Top)
+    } else if goals[Side::Bottom.index()] && position.y - BALL_RADIUS < -config.window_half_height {
+        Some(Side::Bottom)
+    } else {
+        None
+    }
+}
+
+// Bounce the ball off a paddle if they overlap, returns whether they did
+pub fn bounce_off_paddle(
+    ball_position: Vec3,
+    velocity: &mut Vec2,
+    paddle_position: Vec3,
+    paddle_half_size: Vec2
+) -> bool {
+    let b_trans = ball_position;
+
+    if !(b_trans.x + BALL_RADIUS >= paddle_position.x - paddle_half_size.x
+        && b_trans.y - BALL_RADIUS <= paddle_position.y + paddle_half_size.y
+        && b_trans.y + BALL_RADIUS >= paddle_position.y - paddle_half_size.y
+        && b_trans.x - BALL_RADIUS <= paddle_position.x + paddle_half_size.x)
+    {
+        return false;
+    }
+
+    // ball colliding left or right side
+    let hits_x = velocity.x > 0.0 && b_trans.x < paddle_position.x
+        || velocity.x < 0.0 && b_trans.x > paddle_position.x;
+    // ball colliding top or bottom side
+    let hits_y = velocity.y < 0.0 && b_trans.y > paddle_position.y
+        || velocity.y > 0.0 && b_trans.y < paddle_position.y;
+
+    // The long sides of the paddle are the ones facing the ball, so they're checked first
+    // TODO: Here be some bug, which in some cases makes the ball reverse direction instead of bouncing
+    // Maybe fix, or let it be a Feature :)
+    if paddle_half_size.x <= paddle_half_size.y {
+        if hits_x {
+            bounce(&mut velocity.x);
+        } else if hits_y {
+            bounce(&mut velocity.y);
+        }
+    } else if hits_y {
+        bounce(&mut velocity.y);
+    } else if hits_x {
+        bounce(&mut velocity.x);
+    }
+
+    true
+}
+
+// Change direction and accelerate
+fn bounce(velocity: &mut f32) {
+    *velocity = -*velocity;
+
+    if *velocity < 0.0 {
+        *velocity -= BALL_ACCEL;
+    } else {
+        *velocity += BALL_ACCEL;
+    }
+}
+
+// Half size of a paddle of the standard height standing upright
+pub fn paddle_half_size(config: &Config) -> Vec2 {
+    Vec2::new(PADDLE_WIDTH / 2.0, config.paddle_half_height)
+}
+
+// Move a paddle one tick of `speed` along its axis and clamp it inside the arena,
+// `half_length` is half of the paddle's size along that axis
+pub fn move_paddle(config: &Config, position: f32, half_length: f32, direction: MoveDirection, speed: f32) -> f32 {
+    match direction {
+        MoveDirection::UP => clamp_paddle(config.window_half_height, position + speed, half_length),
+        MoveDirection::DOWN => clamp_paddle(config.window_half_height, position - speed, half_length),
+        MoveDirection::RIGHT => clamp_paddle(config.window_half_width, position + speed, half_length),
+        MoveDirection::LEFT => clamp_paddle(config.window_half_width, position - speed, half_length),
+    }
+}
+
+// Keep a paddle of the given size fully inside the arena, `limit` is half of
+// the arena's size along the paddle's axis
+pub fn clamp_paddle(limit: f32, position: f32, half_length: f32) -> f32 {
+    position.clamp(-limit + half_length, limit - half_length)
+}
+
+pub fn ball_start_velocity<R: Rng>(rng: &mut R) -> Vec2 {
+    let x: f32 = rng.gen_range(-1.0..=1.0);
+    let y: f32 = rng.gen_range(-1.0..=1.0);
+    Vec2::new(x, y).normalize() * BALL_SPAWN_SPEED
+}
@@ -0,0 +1,523 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use super::{AppState, Ball, BallAssets, Config, Eliminated, MatchRules, Paddle, Side, BALL_RADIUS, add_ball};
+use super::net::HostSession;
+use super::physics;
+
+pub struct PowerUpPlugin;
+
+// A pickup waiting in the arena, collected by the last paddle to touch
+// the ball when the ball passes through it
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerUp {
+    GrowPaddle,
+    ShrinkOpponent,
+    SpeedUpBall,
+    SlowDownBall,
+    MultiBall,
+}
+
+pub struct PowerUpSpawner {
+    // Seconds of play until the next pickup appears
+    next_spawn: f32,
+}
+
+struct ActiveEffect {
+    power_up: PowerUp,
+    // The paddles whose size changes, ball effects don't have any
+    targets: Vec<Entity>,
+    remaining: f32,
+    // Side of the player who collected it
+    side: Side,
+    hud_icon: Entity,
+}
+
+pub struct ActiveEffects(Vec<ActiveEffect>);
+
+// Pickups and effects in play, kept with a saved match. The speed effects on each ball are saved with the ball.
+#[derive(Serialize, Deserialize)]
+pub struct SavedPowerUps {
+    next_spawn: f32,
+    pickups: Vec<(PowerUp, [f32; 3])>,
+    effects: Vec<SavedEffect>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEffect {
+    power_up: PowerUp,
+    // Indices into the paddles the power-ups were saved with
+    targets: Vec<usize>,
+    remaining: f32,
+    side: Side,
+}
+
+// Remaining effects are listed next to the score of the player who collected them, indexed by Side
+pub struct PowerUpHud([Entity; 4]);
+
+#[derive(Component)]
+struct PowerUpIconText;
+
+const PICKUP_SIZE: f32 = 40.0;
+const MAX_PICKUPS: usize = 2;
+const SPAWN_INTERVAL: (f32, f32) = (4.0, 10.0);
+const EFFECT_DURATION: f32 = 8.0;
+const GROW_FACTOR: f32 = 1.5;
+const SHRINK_FACTOR: f32 = 0.6;
+const SPEED_UP_FACTOR: f32 = 1.4;
+const SLOW_DOWN_FACTOR: f32 = 0.6;
+// Directions of the extra balls relative to the collecting ball, in radians
+const MULTI_BALL_ANGLES: [f32; 2] = [-0.5, 0.5];
+
+impl PowerUp {
+    const ALL: [PowerUp; 5] = [
+        PowerUp::GrowPaddle,
+        PowerUp::ShrinkOpponent,
+        PowerUp::SpeedUpBall,
+        PowerUp::SlowDownBall,
+        PowerUp::MultiBall,
+    ];
+
+    fn color(self) -> Color {
+        match self {
+            PowerUp::GrowPaddle => Color::rgb(0.2, 0.8, 0.2),
+            PowerUp::ShrinkOpponent => Color::rgb(0.9, 0.2, 0.2),
+            PowerUp::SpeedUpBall => Color::rgb(0.92, 0.39, 0.20),
+            PowerUp::SlowDownBall => Color::rgb(0.2, 0.5, 0.9),
+            PowerUp::MultiBall => Color::rgb(1.0, 0.85, 0.2),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PowerUp::GrowPaddle => "Grow",
+            PowerUp::ShrinkOpponent => "Shrink",
+            PowerUp::SpeedUpBall => "Fast ball",
+            PowerUp::SlowDownBall => "Slow ball",
+            PowerUp::MultiBall => "Multi-ball",
+        }
+    }
+
+    fn ball_speed_factor(self) -> Option<f32> {
+        match self {
+            PowerUp::SpeedUpBall => Some(SPEED_UP_FACTOR),
+            PowerUp::SlowDownBall => Some(SLOW_DOWN_FACTOR),
+            _ => None
+        }
+    }
+
+    fn paddle_size_factor(self) -> f32 {
+        match self {
+            PowerUp::GrowPaddle => GROW_FACTOR,
+            PowerUp::ShrinkOpponent => SHRINK_FACTOR,
+            _ => 1.0
+        }
+    }
+}
+
+impl PowerUpHud {
+    fn column(&self, side: Side) -> Entity {
+        self.0[side.index()]
+    }
+}
+
+impl SavedPowerUps {
+    // Target paddles are saved by their index in `paddles`
+    pub fn new(
+        spawner: &PowerUpSpawner,
+        effects: &ActiveEffects,
+        pickup_query: &Query<(&Transform, &PowerUp)>,
+        paddles: &[Entity]
+    ) -> Self {
+        SavedPowerUps {
+            next_spawn: spawner.next_spawn,
+            pickups: pickup_query.iter()
+                .map(|(transform, power_up)| (*power_up, transform.translation.to_array()))
+                .collect(),
+            effects: effects.0.iter()
+                .map(|effect| SavedEffect {
+                    power_up: effect.power_up,
+                    targets: effect.targets.iter()
+                        .filter_map(|target| paddles.iter().position(|paddle| paddle == target))
+                        .collect(),
+                    remaining: effect.remaining,
+                    side: effect.side,
+                })
+                .collect(),
+        }
+    }
+
+    // Puts the power-ups back into an arena cleared by the restart, `paddles` are
+    // the paddles matching the saved ones
+    pub fn restore(
+        &self,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        hud: &PowerUpHud,
+        spawner: &mut PowerUpSpawner,
+        effects: &mut ActiveEffects,
+        paddles: &[Option<Entity>]
+    ) {
+        spawner.next_spawn = self.next_spawn;
+
+        for (power_up, position) in &self.pickups {
+            spawn_pickup(commands, *power_up, Vec3::from(*position));
+        }
+
+        for saved in &self.effects {
+            let targets: Vec<Entity> = saved.targets.iter()
+                .filter_map(|index| paddles.get(*index).copied().flatten())
+                .collect();
+
+            // A paddle effect whose paddles are all gone is over
+            if targets.is_empty() && !saved.targets.is_empty() {
+                continue;
+            }
+
+            effects.0.push(ActiveEffect {
+                power_up: saved.power_up,
+                targets,
+                remaining: saved.remaining,
+                side: saved.side,
+                hud_icon: spawn_hud_icon(commands, asset_server, hud.column(saved.side), saved.power_up),
+            });
+        }
+    }
+}
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(PowerUpSpawner { next_spawn: SPAWN_INTERVAL.0 })
+        .insert_resource(ActiveEffects(Vec::new()))
+        .add_startup_system(setup_hud)
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(spawn_power_ups)
+                .with_system(collect_power_ups)
+                .with_system(tick_effects.label("tick_effects"))
+                .with_system(resize_paddles.after("tick_effects"))
+                .with_system(update_hud.after("tick_effects"))
+        )
+        // Effects and pickups only last for the point they were collected on
+        .add_system_set(
+            SystemSet::on_enter(AppState::Reset)
+                .with_system(clear_power_ups)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(clear_power_ups)
+        );
+    }
+}
+
+fn setup_hud(mut commands: Commands, windows: Res<Windows>) {
+    let window = windows.get_primary().unwrap();
+
+    let column = |position: Rect<Val>| {
+        commands.spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        }).id()
+    };
+
+    // The top and bottom players' columns go inside their lives counters
+    let columns = [
+        Rect { top: Val::Px(60.0), left: Val::Px(window.width() / 2.0 - 200.0), ..Default::default() },
+        Rect { top: Val::Px(60.0), left: Val::Px(window.width() / 2.0 + 50.0), ..Default::default() },
+        Rect { top: Val::Px(110.0), left: Val::Px(window.width() / 2.0 - 75.0), ..Default::default() },
+        Rect { bottom: Val::Px(110.0), left: Val::Px(window.width() / 2.0 - 75.0), ..Default::default() },
+    ];
+
+    commands.insert_resource(PowerUpHud(columns.map(column)));
+}
+
+fn spawn_power_ups(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<Config>,
+    host_session: Option<Res<HostSession>>,
+    mut spawner: ResMut<PowerUpSpawner>,
+    pickup_query: Query<&PowerUp>
+) {
+    // Snapshots don't carry pickups, so network matches are played without them
+    if host_session.is_some() {
+        return;
+    }
+
+    spawner.next_spawn -= time.delta_seconds();
+
+    if spawner.next_spawn > 0.0 {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    spawner.next_spawn = rng.gen_range(SPAWN_INTERVAL.0..=SPAWN_INTERVAL.1);
+
+    if pickup_query.iter().count() >= MAX_PICKUPS {
+        return;
+    }
+
+    // Somewhere between the paddles, away from the ceiling and floor
+    let max_x = config.window_half_width - 250.0;
+    let max_y = config.window_half_height - 60.0;
+    let position = Vec3::new(rng.gen_range(-max_x..=max_x), rng.gen_range(-max_y..=max_y), 0.0);
+    let power_up = PowerUp::ALL[rng.gen_range(0..PowerUp::ALL.len())];
+
+    spawn_pickup(&mut commands, power_up, position);
+}
+
+fn spawn_pickup(commands: &mut Commands, power_up: PowerUp, position: Vec3) {
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: power_up.color(),
+            ..Default::default()
+        },
+        transform: Transform {
+            scale: Vec3::new(PICKUP_SIZE, PICKUP_SIZE, 0.0),
+            translation: position,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .insert(power_up);
+}
+
+fn collect_power_ups(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    hud: Res<PowerUpHud>,
+    ball_assets: Res<BallAssets>,
+    mut effects: ResMut<ActiveEffects>,
+    mut ball_query: Query<(&Transform, &mut Ball)>,
+    pickup_query: Query<(Entity, &Transform, &PowerUp)>,
+    paddle_query: Query<(Entity, &Paddle), Without<Eliminated>>
+) {
+    // Pickups and the ball that went through them, a pickup only goes to one ball
+    let mut collected: Vec<(Entity, PowerUp, Entity, Vec3, Vec2, Vec<PowerUp>)> = Vec::new();
+
+    for (ball_transform, ball) in ball_query.iter() {
+        // Nobody has touched this ball yet
+        let owner = match ball.last_paddle {
+            Some(owner) => owner,
+            None => continue
+        };
+
+        for (entity, transform, power_up) in pickup_query.iter() {
+            let distance = ball_transform.translation.truncate().distance(transform.translation.truncate());
+
+            if distance <= BALL_RADIUS + PICKUP_SIZE / 2.0 && !collected.iter().any(|(pickup, ..)| *pickup == entity) {
+                collected.push((entity, *power_up, owner, ball_transform.translation, ball.velocity, ball.speed_effects.clone()));
+            }
+        }
+    }
+
+    for (entity, power_up, owner, position, velocity, speed_effects) in collected {
+        commands.entity(entity).despawn();
+
+        // Extra balls split off the collecting one and count as touched by the same paddle,
+        // they keep its speed and so the speed effects that have to be undone later
+        if power_up == PowerUp::MultiBall {
+            for angle in MULTI_BALL_ANGLES {
+                add_ball(&mut commands, &ball_assets, position, Ball {
+                    velocity: Mat2::from_angle(angle) * velocity,
+                    last_paddle: Some(owner),
+                    speed_effects: speed_effects.clone(),
+                });
+            }
+            continue;
+        }
+
+        // A paddle knocked out since it last touched the ball collects nothing
+        let side = match paddle_query.get(owner) {
+            Ok((_, paddle)) => paddle.side,
+            Err(_) => continue
+        };
+
+        // Shrinking hits every opponent, both paddles of the other team in doubles
+        let targets: Vec<Entity> = match power_up {
+            PowerUp::GrowPaddle => vec![owner],
+            PowerUp::ShrinkOpponent => paddle_query.iter()
+                .filter(|(_, paddle)| paddle.side != side)
+                .map(|(entity, _)| entity)
+                .collect(),
+            _ => Vec::new()
+        };
+
+        // Balls put in play since the effect started get it too
+        if let Some(factor) = power_up.ball_speed_factor() {
+            for (_, mut ball) in ball_query.iter_mut() {
+                if !ball.speed_effects.contains(&power_up) {
+                    ball.velocity *= factor;
+                    ball.speed_effects.push(power_up);
+                }
+            }
+        }
+
+        // Collecting an effect that's already active just starts its timer over
+        if let Some(effect) = effects.0.iter_mut().find(|effect| effect.power_up == power_up && effect.targets == targets) {
+            effect.remaining = EFFECT_DURATION;
+            continue;
+        }
+
+        let hud_icon = spawn_hud_icon(&mut commands, &asset_server, hud.column(side), power_up);
+
+        effects.0.push(ActiveEffect {
+            power_up,
+            targets,
+            remaining: EFFECT_DURATION,
+            side,
+            hud_icon,
+        });
+    }
+}
+
+fn tick_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut effects: ResMut<ActiveEffects>,
+    mut ball_query: Query<&mut Ball>
+) {
+    let delta = time.delta_seconds();
+
+    for effect in effects.0.iter_mut() {
+        effect.remaining -= delta;
+    }
+
+    // Only the balls the effect was applied to slow down or speed up again
+    for effect in effects.0.iter().filter(|effect| effect.remaining <= 0.0) {
+        if let Some(factor) = effect.power_up.ball_speed_factor() {
+            for mut ball in ball_query.iter_mut() {
+                if let Some(index) = ball.speed_effects.iter().position(|power_up| *power_up == effect.power_up) {
+                    ball.velocity /= factor;
+                    ball.speed_effects.remove(index);
+                }
+            }
+        }
+
+        commands.entity(effect.hud_icon).despawn_recursive();
+    }
+
+    effects.0.retain(|effect| effect.remaining > 0.0);
+}
+
+// Paddle lengths follow the handicaps and active effects, clamped back inside the arena when they grow
+fn resize_paddles(
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    effects: Res<ActiveEffects>,
+    mut paddle_query: Query<(Entity, &Paddle, &mut Transform)>
+) {
+    for (entity, paddle, mut transform) in paddle_query.iter_mut() {
+        let factor: f32 = effects.0.iter()
+            .filter(|effect| effect.targets.contains(&entity))
+            .map(|effect| effect.power_up.paddle_size_factor())
+            .product();
+        let length = rules.paddle_length(&config, paddle.side) * factor;
+
+        if paddle.side.is_horizontal() {
+            if transform.scale.x != length {
+                transform.scale.x = length;
+                transform.translation.x = physics::clamp_paddle(config.window_half_width, transform.translation.x, length / 2.0);
+            }
+        } else if transform.scale.y != length {
+            transform.scale.y = length;
+            transform.translation.y = physics::clamp_paddle(config.window_half_height, transform.translation.y, length / 2.0);
+        }
+    }
+}
+
+fn update_hud(effects: Res<ActiveEffects>, children_query: Query<&Children>, mut text_query: Query<&mut Text, With<PowerUpIconText>>) {
+    for effect in effects.0.iter() {
+        let children = match children_query.get(effect.hud_icon) {
+            Ok(children) => children,
+            Err(_) => continue
+        };
+
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                let value = format!("{} {:.0}s", effect.power_up.name(), effect.remaining.ceil());
+
+                if text.sections[0].value != value {
+                    text.sections[0].value = value;
+                }
+            }
+        }
+    }
+}
+
+fn clear_power_ups(
+    mut commands: Commands,
+    mut spawner: ResMut<PowerUpSpawner>,
+    mut effects: ResMut<ActiveEffects>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    pickup_query: Query<Entity, With<PowerUp>>,
+    mut paddle_query: Query<(&Paddle, &mut Transform)>
+) {
+    for entity in pickup_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    // The ball gets a fresh velocity on reset, so only the paddles need restoring
+    for effect in effects.0.drain(..) {
+        commands.entity(effect.hud_icon).despawn_recursive();
+    }
+
+    for (paddle, mut transform) in paddle_query.iter_mut() {
+        let length = rules.paddle_length(&config, paddle.side);
+
+        if paddle.side.is_horizontal() {
+            transform.scale.x = length;
+        } else {
+            transform.scale.y = length;
+        }
+    }
+
+    spawner.next_spawn = SPAWN_INTERVAL.0;
+}
+
+fn spawn_hud_icon(commands: &mut Commands, asset_server: &AssetServer, column: Entity, power_up: PowerUp) -> Entity {
+    let icon = commands.spawn_bundle(NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            margin: Rect::all(Val::Px(2.0)),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(16.0), Val::Px(16.0)),
+                margin: Rect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            color: power_up.color().into(),
+            ..Default::default()
+        });
+
+        parent.spawn_bundle(TextBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 16.0,
+                    color: Color::rgb(1.0, 1.0, 1.0),
+                },
+                Default::default()
+            ),
+            ..Default::default()
+        }).insert(PowerUpIconText);
+    }).id();
+
+    commands.entity(column).push_children(&[icon]);
+    icon
+}
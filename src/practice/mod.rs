@@ -0,0 +1,186 @@
+use bevy::prelude::*;
+use super::{AppState, Config, GameMode, MatchRules, Player2, ScoreBoard};
+use super::events::RunEnded;
+use super::four_player::Eliminated;
+use super::storage;
+
+pub struct PracticePlugin;
+
+// Time survived in the current run, only counted in game so pausing doesn't add to it
+pub struct SurvivalTime(pub f32);
+
+// The best run so far, most hits first and the longest time to break ties
+pub struct PersonalBest {
+    pub hits: i32,
+    pub seconds: f32,
+    // The current run went past the old record
+    pub beaten: bool,
+}
+
+#[derive(Component)]
+struct PracticeWall;
+
+#[derive(Component)]
+struct PracticeText;
+
+const BEST_FILE: &str = "practice.best";
+const WALL_THICKNESS: f32 = 10.0;
+const WALL_COLOR: Color = Color::rgb(0.55, 0.55, 0.55);
+
+impl Plugin for PracticePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(SurvivalTime(0.0))
+        .insert_resource(load_best())
+        .add_startup_system(setup_practice_text)
+        .add_system(practice_text)
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                // Four player setup brings back every paddle, player 2 has to leave after that
+                .with_system(setup_practice.after("setup_four_player"))
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(track_run)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(save_best)
+        );
+    }
+}
+
+// The file holds the hits and seconds of the best run on one line
+fn load_best() -> PersonalBest {
+    let mut best = PersonalBest { hits: 0, seconds: 0.0, beaten: false };
+
+    if let Some(contents) = storage::load(BEST_FILE) {
+        let mut values = contents.split_whitespace();
+
+        match (values.next().map(str::parse), values.next().map(str::parse)) {
+            (Some(Ok(hits)), Some(Ok(seconds))) => {
+                best.hits = hits;
+                best.seconds = seconds;
+            },
+            _ => warn!("Ignoring broken personal best in {}", BEST_FILE),
+        }
+    }
+
+    best
+}
+
+fn save_best(mut runs_ended: EventReader<RunEnded>, best: Res<PersonalBest>) {
+    if let Some(run) = runs_ended.iter().last() {
+        if best.beaten {
+            info!("New practice best: {} hits in {:.1} s", run.hits, best.seconds);
+            storage::save(BEST_FILE, &format!("{} {}\n", best.hits, best.seconds));
+        }
+    }
+}
+
+// Player 2 sits out practice runs and their side becomes a wall
+fn setup_practice(
+    mut commands: Commands,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    mut time: ResMut<SurvivalTime>,
+    mut best: ResMut<PersonalBest>,
+    mut player2_query: Query<(Entity, &mut Visibility), With<Player2>>,
+    wall_query: Query<Entity, With<PracticeWall>>
+) {
+    for entity in wall_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    time.0 = 0.0;
+    best.beaten = false;
+
+    if rules.mode != GameMode::Practice {
+        return;
+    }
+
+    let (player2, mut visibility) = player2_query.single_mut();
+    commands.entity(player2).insert(Eliminated);
+    visibility.is_visible = false;
+
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: WALL_COLOR,
+            ..Default::default()
+        },
+        transform: Transform {
+            scale: Vec3::new(WALL_THICKNESS, config.window_half_height * 2.0, 0.0),
+            translation: Vec3::new(config.window_half_width - WALL_THICKNESS / 2.0, 0.0, 0.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .insert(PracticeWall);
+}
+
+fn track_run(
+    time: Res<Time>,
+    rules: Res<MatchRules>,
+    scoreboard: Res<ScoreBoard>,
+    mut survival_time: ResMut<SurvivalTime>,
+    mut best: ResMut<PersonalBest>
+) {
+    if rules.mode != GameMode::Practice {
+        return;
+    }
+
+    survival_time.0 += time.delta_seconds();
+
+    let hits = scoreboard.hits;
+
+    if hits > best.hits || (hits == best.hits && hits > 0 && survival_time.0 > best.seconds) {
+        best.hits = hits;
+        best.seconds = survival_time.0;
+        best.beaten = true;
+    }
+}
+
+fn setup_practice_text(mut commands: Commands, asset_server: Res<AssetServer>, windows: Res<Windows>) {
+    let window = windows.get_primary().unwrap();
+
+    commands.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 20.0,
+                color: Color::rgb(1.0, 1.0, 1.0),
+            },
+            Default::default()
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(60.0),
+                left: Val::Px(window.width() / 2.0 + 50.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(PracticeText);
+}
+
+fn practice_text(
+    rules: Res<MatchRules>,
+    survival_time: Res<SurvivalTime>,
+    best: Res<PersonalBest>,
+    mut text_query: Query<&mut Text, With<PracticeText>>
+) {
+    let value = if rules.mode == GameMode::Practice {
+        format!("{:.1} s\nBest: {} hits, {:.1} s", survival_time.0, best.hits, best.seconds)
+    } else {
+        String::new()
+    };
+
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+use super::{AppState, GameMode, Keys, MatchRules, Players, ScoreBoard, Side, PADDLE_COLORS};
+use super::net::{ClientSession, HostSession};
+use super::storage;
+use super::ui::{self, TEXT_COLOR, text_bundle};
+
+pub struct ProfilesPlugin;
+
+// A local player who keeps their settings and stats between matches, found by name
+pub struct Profile {
+    pub name: String,
+    pub color: usize,
+    pub keys: Keys,
+    pub stats: ProfileStats,
+}
+
+#[derive(Default)]
+pub struct ProfileStats {
+    pub matches: u32,
+    pub wins: u32,
+    pub points: i32,
+    pub longest_rally: i32,
+    pub fastest_hit: f32,
+}
+
+pub struct Profiles(pub Vec<Profile>);
+
+impl Profiles {
+    pub fn find(&self, name: &str) -> Option<&Profile> {
+        self.0.iter().find(|profile| profile.name == name)
+    }
+
+    fn find_mut(&mut self, name: &str) -> Option<&mut Profile> {
+        self.0.iter_mut().find(|profile| profile.name == name)
+    }
+}
+
+struct ProfilesScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for ProfilesScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+const PROFILES_FILE: &str = "profiles";
+
+const COLUMN_WIDTH: f32 = 150.0;
+
+impl Plugin for ProfilesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(load_profiles())
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(record_match)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Profiles)
+                .with_system(setup_profiles_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Profiles)
+                .with_system(ui::back_controls)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Profiles)
+                .with_system(ui::cleanup::<ProfilesScreen>)
+        );
+    }
+}
+
+// One profile per line, the name goes last since it can have spaces in it
+fn load_profiles() -> Profiles {
+    let mut profiles = Profiles(Vec::new());
+
+    let contents = match storage::load(PROFILES_FILE) {
+        Some(contents) => contents,
+        None => return profiles
+    };
+
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_profile(line) {
+            Some(profile) if profiles.find(&profile.name).is_none() => profiles.0.push(profile),
+            _ => warn!("Ignoring profile '{}' in {}", line, PROFILES_FILE),
+        }
+    }
+
+    profiles
+}
+
+fn parse_profile(line: &str) -> Option<Profile> {
+    let mut parts = line.splitn(8, ' ');
+
+    let stats = ProfileStats {
+        matches: parts.next()?.parse().ok()?,
+        wins: parts.next()?.parse().ok()?,
+        points: parts.next()?.parse().ok()?,
+        longest_rally: parts.next()?.parse().ok()?,
+        fastest_hit: parts.next()?.parse().ok()?,
+    };
+    let color = parts.next()?.parse::<usize>().ok().filter(|color| *color < PADDLE_COLORS.len())?;
+    let keys = Keys::from_id(parts.next()?)?;
+    let name = parts.next().filter(|name| !name.is_empty())?.to_string();
+
+    Some(Profile { name, color, keys, stats })
+}
+
+fn save_profiles(profiles: &Profiles) {
+    let contents: String = profiles.0.iter()
+        .map(|profile| {
+            let stats = &profile.stats;
+
+            format!(
+                "{} {} {} {} {} {} {} {}\n",
+                stats.matches,
+                stats.wins,
+                stats.points,
+                stats.longest_rally,
+                stats.fastest_hit,
+                profile.color,
+                profile.keys.id(),
+                profile.name
+            )
+        })
+        .collect();
+
+    storage::save(PROFILES_FILE, &contents);
+}
+
+// Named human players get a profile when a match starts, and the profile remembers their latest choices
+pub fn remember_players(profiles: &mut Profiles, players: &Players, mode: GameMode) {
+    for seat in mode.seats() {
+        let settings = &players.0[seat.index()];
+
+        if settings.name.is_empty() || !players.is_human_at(seat) {
+            continue;
+        }
+
+        match profiles.find_mut(&settings.name) {
+            Some(profile) => {
+                profile.color = settings.color;
+                profile.keys = settings.keys;
+            },
+            None => profiles.0.push(Profile {
+                name: settings.name.clone(),
+                color: settings.color,
+                keys: settings.keys,
+                stats: ProfileStats::default(),
+            }),
+        }
+    }
+
+    save_profiles(profiles);
+}
+
+// Practice runs keep their own record, and network matches are left out since
+// only one of the players is on this machine
+fn record_match(
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    scoreboard: Res<ScoreBoard>,
+    host_session: Option<Res<HostSession>>,
+    client_session: Option<Res<ClientSession>>,
+    mut profiles: ResMut<Profiles>
+) {
+    if rules.mode == GameMode::Practice || host_session.is_some() || client_session.is_some() {
+        return;
+    }
+
+    let winner = scoreboard.winner(&rules);
+    let mut changed = false;
+
+    // Both players of a doubles team share its points and result
+    for seat in rules.mode.seats() {
+        let side = seat.side;
+        let settings = &players.0[seat.index()];
+
+        if settings.name.is_empty() || !players.is_human_at(seat) {
+            continue;
+        }
+
+        let profile = match profiles.find_mut(&settings.name) {
+            Some(profile) => profile,
+            None => continue
+        };
+
+        let points = match side {
+            Side::Left => scoreboard.player1,
+            Side::Right => scoreboard.player2,
+            // Four players count lives, not points
+            _ => 0
+        };
+
+        let stats = &mut profile.stats;
+        stats.matches += 1;
+        stats.points += points;
+        stats.longest_rally = stats.longest_rally.max(scoreboard.longest_rally);
+        stats.fastest_hit = stats.fastest_hit.max(scoreboard.fastest_hits[side.index()]);
+
+        if winner == Some(side.index() as i32 + 1) {
+            stats.wins += 1;
+        }
+
+        changed = true;
+    }
+
+    if changed {
+        save_profiles(&profiles);
+    }
+}
+
+fn setup_profiles_screen(mut commands: Commands, asset_server: Res<AssetServer>, profiles: Res<Profiles>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    let ui_root = commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("Profiles", 50.0, TEXT_COLOR, &font));
+
+        if profiles.0.is_empty() {
+            parent.spawn_bundle(text_bundle(
+                "No profiles yet, give yourself a name in the match setup",
+                20.0,
+                TEXT_COLOR,
+                &font
+            ));
+        } else {
+            ui::spawn_row(parent, &["Name", "Matches", "Wins", "Points", "Longest rally", "Fastest hit"].map(String::from), COLUMN_WIDTH, Color::rgb(0.55, 0.55, 0.55), &font);
+        }
+
+        for profile in profiles.0.iter() {
+            let stats = &profile.stats;
+
+            ui::spawn_row(parent, &[
+                profile.name.clone(),
+                stats.matches.to_string(),
+                stats.wins.to_string(),
+                stats.points.to_string(),
+                stats.longest_rally.to_string(),
+                format!("{:.1}", stats.fastest_hit),
+            ], COLUMN_WIDTH, PADDLE_COLORS[profile.color], &font);
+        }
+
+        ui::spawn_back_button(parent, &font);
+    }).id();
+
+    commands.insert_resource(ProfilesScreen { ui_root });
+}
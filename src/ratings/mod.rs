@@ -0,0 +1,322 @@
+use bevy::prelude::*;
+use super::{AppState, GameMode, Handicap, MatchRules, Players, ScoreBoard, Side, PADDLE_COLORS};
+use super::net::{ClientSession, HostSession};
+use super::profiles::Profiles;
+use super::storage;
+use super::ui::{self, TEXT_COLOR, text_bundle};
+
+pub struct RatingsPlugin;
+
+// Elo rating of a profile, with the ranked matches behind it
+pub struct Rating {
+    pub name: String,
+    pub rating: f32,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+pub struct Ratings(pub Vec<Rating>);
+
+impl Ratings {
+    fn find_or_insert(&mut self, name: &str) -> &mut Rating {
+        match self.0.iter().position(|rating| rating.name == name) {
+            Some(index) => &mut self.0[index],
+            None => {
+                self.0.push(Rating { name: name.to_string(), rating: STARTING_RATING, wins: 0, losses: 0 });
+                self.0.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+// How the last match moved the ratings, empty if it wasn't ranked
+pub struct RatingChanges(pub Vec<RatingChange>);
+
+pub struct RatingChange {
+    pub side: Side,
+    pub rating: f32,
+    pub delta: f32,
+}
+
+struct LeaderboardScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for LeaderboardScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+const RATINGS_FILE: &str = "ratings";
+const STARTING_RATING: f32 = 1200.0;
+// Most a rating can move in one match
+const K_FACTOR: f32 = 32.0;
+
+const COLUMN_WIDTH: f32 = 150.0;
+
+impl Plugin for RatingsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(load_ratings())
+        .insert_resource(RatingChanges(Vec::new()))
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(update_ratings.label("update_ratings"))
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Leaderboard)
+                .with_system(setup_leaderboard_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Leaderboard)
+                .with_system(ui::back_controls)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Leaderboard)
+                .with_system(ui::cleanup::<LeaderboardScreen>)
+        );
+    }
+}
+
+// One profile per line, the name goes last since it can have spaces in it
+fn load_ratings() -> Ratings {
+    let mut ratings = Ratings(Vec::new());
+
+    let contents = match storage::load(RATINGS_FILE) {
+        Some(contents) => contents,
+        None => return ratings
+    };
+
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_rating(line) {
+            Some(rating) => ratings.0.push(rating),
+            None => warn!("Ignoring rating '{}' in {}", line, RATINGS_FILE),
+        }
+    }
+
+    ratings
+}
+
+fn parse_rating(line: &str) -> Option<Rating> {
+    let mut parts = line.splitn(4, ' ');
+
+    let rating = parts.next()?.parse().ok()?;
+    let wins = parts.next()?.parse().ok()?;
+    let losses = parts.next()?.parse().ok()?;
+    let name = parts.next().filter(|name| !name.is_empty())?.to_string();
+
+    Some(Rating { name, rating, wins, losses })
+}
+
+fn save_ratings(ratings: &Ratings) {
+    let contents: String = ratings.0.iter()
+        .map(|rating| format!("{} {} {} {}\n", rating.rating, rating.wins, rating.losses, rating.name))
+        .collect();
+
+    storage::save(RATINGS_FILE, &contents);
+}
+
+// Ranked matches are one on one between two different profiles on this machine, on even terms
+fn is_ranked(rules: &MatchRules, players: &Players, networked: bool) -> bool {
+    let [left, right] = [Side::Left, Side::Right].map(|side| &players.0[side.index()]);
+
+    // Teams and more than two sides have no single opponent to rate against
+    let one_on_one = match rules.mode {
+        GameMode::Classic | GameMode::MultiBall | GameMode::Breakout | GameMode::TimeAttack => true,
+        GameMode::FourPlayer | GameMode::Doubles | GameMode::Practice => false,
+    };
+
+    !networked
+        && one_on_one
+        && !left.name.is_empty()
+        && !right.name.is_empty()
+        && left.name != right.name
+        && players.is_human(Side::Left)
+        && players.is_human(Side::Right)
+        && rules.handicaps.iter().all(|handicap| *handicap == Handicap::default())
+}
+
+fn expected_score(rating: f32, opponent: f32) -> f32 {
+    1.0 / (1.0 + 10f32.powf((opponent - rating) / 400.0))
+}
+
+fn update_ratings(
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    scoreboard: Res<ScoreBoard>,
+    host_session: Option<Res<HostSession>>,
+    client_session: Option<Res<ClientSession>>,
+    mut ratings: ResMut<Ratings>,
+    mut changes: ResMut<RatingChanges>
+) {
+    changes.0.clear();
+
+    let winner = match scoreboard.winner(&rules) {
+        Some(winner) if is_ranked(&rules, &players, host_session.is_some() || client_session.is_some()) => Side::ALL[winner as usize - 1],
+        _ => return
+    };
+
+    let names = [Side::Left, Side::Right].map(|side| players.0[side.index()].name.as_str());
+    changes.0 = rate_match(&mut ratings, names, winner);
+
+    save_ratings(&ratings);
+}
+
+// Moves the ratings of the left and right player after a match between them
+fn rate_match(ratings: &mut Ratings, names: [&str; 2], winner: Side) -> Vec<RatingChange> {
+    let left = ratings.find_or_insert(names[0]).rating;
+    let right = ratings.find_or_insert(names[1]).rating;
+
+    [(Side::Left, names[0], left, right), (Side::Right, names[1], right, left)].into_iter()
+        .map(|(side, name, rating, opponent)| {
+            let won = side == winner;
+            let delta = K_FACTOR * (if won { 1.0 } else { 0.0 } - expected_score(rating, opponent));
+
+            let entry = ratings.find_or_insert(name);
+            entry.rating += delta;
+
+            if won {
+                entry.wins += 1;
+            } else {
+                entry.losses += 1;
+            }
+
+            RatingChange { side, rating: entry.rating, delta }
+        })
+        .collect()
+}
+
+fn setup_leaderboard_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ratings: Res<Ratings>,
+    profiles: Res<Profiles>
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    let mut ranking: Vec<&Rating> = ratings.0.iter().collect();
+    ranking.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+
+    let ui_root = commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("Leaderboard", 50.0, TEXT_COLOR, &font));
+
+        if ranking.is_empty() {
+            parent.spawn_bundle(text_bundle(
+                "No ranked matches yet, play one on one between two profiles without handicaps",
+                20.0,
+                TEXT_COLOR,
+                &font
+            ));
+        } else {
+            ui::spawn_row(parent, &["#", "Name", "Rating", "Wins", "Losses"].map(String::from), COLUMN_WIDTH, Color::rgb(0.55, 0.55, 0.55), &font);
+        }
+
+        for (index, rating) in ranking.iter().enumerate() {
+            // Profiles keep their color, ratings can outlive them
+            let color = profiles.find(&rating.name).map_or(TEXT_COLOR, |profile| PADDLE_COLORS[profile.color]);
+
+            ui::spawn_row(parent, &[
+                (index + 1).to_string(),
+                rating.name.clone(),
+                format!("{:.0}", rating.rating),
+                rating.wins.to_string(),
+                rating.losses.to_string(),
+            ], COLUMN_WIDTH, color, &font);
+        }
+
+        ui::spawn_back_button(parent, &font);
+    }).id();
+
+    commands.insert_resource(LeaderboardScreen { ui_root });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(ratings: &Ratings, name: &str) -> f32 {
+        ratings.0.iter().find(|rating| rating.name == name).unwrap().rating
+    }
+
+    #[test]
+    fn expected_score_is_even_between_equals() {
+        assert_eq!(expected_score(1500.0, 1500.0), 0.5);
+    }
+
+    #[test]
+    fn expected_score_favours_the_higher_rating() {
+        // 400 points ahead is ten to one
+        assert!((expected_score(1600.0, 1200.0) - 10.0 / 11.0).abs() < 1e-5);
+        assert!((expected_score(1200.0, 1600.0) - 1.0 / 11.0).abs() < 1e-5);
+
+        for (rating, opponent) in [(1200.0, 1250.0), (900.0, 1800.0), (2000.0, 1000.0)] {
+            let sum = expected_score(rating, opponent) + expected_score(opponent, rating);
+            assert!((sum - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn new_players_start_even() {
+        let mut ratings = Ratings(Vec::new());
+        let changes = rate_match(&mut ratings, ["Ann", "Bob"], Side::Right);
+
+        assert_eq!(rating(&ratings, "Ann"), STARTING_RATING - K_FACTOR / 2.0);
+        assert_eq!(rating(&ratings, "Bob"), STARTING_RATING + K_FACTOR / 2.0);
+
+        let deltas: Vec<(Side, f32)> = changes.iter().map(|change| (change.side, change.delta)).collect();
+        assert_eq!(deltas, vec![(Side::Left, -K_FACTOR / 2.0), (Side::Right, K_FACTOR / 2.0)]);
+
+        let bob = ratings.0.iter().find(|rating| rating.name == "Bob").unwrap();
+        assert_eq!((bob.wins, bob.losses), (1, 0));
+    }
+
+    #[test]
+    fn ratings_move_by_the_surprise() {
+        let mut ratings = Ratings(vec![
+            Rating { name: "Favourite".to_string(), rating: 1600.0, wins: 10, losses: 0 },
+            Rating { name: "Underdog".to_string(), rating: 1200.0, wins: 0, losses: 10 },
+        ]);
+
+        let expected = rate_match(&mut ratings, ["Favourite", "Underdog"], Side::Left);
+        let upset = rate_match(&mut ratings, ["Favourite", "Underdog"], Side::Right);
+
+        // Points only change hands, and an upset is worth more than the expected result
+        for changes in [&expected, &upset] {
+            assert!((changes[0].delta + changes[1].delta).abs() < 1e-4);
+            assert!(changes.iter().all(|change| change.delta.abs() <= K_FACTOR));
+        }
+
+        assert!(expected[0].delta > 0.0 && expected[0].delta < K_FACTOR / 2.0);
+        assert!(upset[1].delta > K_FACTOR / 2.0);
+        assert_eq!(upset[1].rating, rating(&ratings, "Underdog"));
+    }
+
+    #[test]
+    fn only_one_on_one_modes_are_ranked() {
+        let mut players = Players::default();
+        players.0[Side::Left.index()].name = "Ann".to_string();
+        players.0[Side::Right.index()].name = "Bob".to_string();
+
+        let ranked = |mode| is_ranked(&MatchRules { mode, ..Default::default() }, &players, false);
+
+        assert!(ranked(GameMode::Classic));
+        assert!(ranked(GameMode::MultiBall));
+        assert!(!ranked(GameMode::Doubles));
+        assert!(!ranked(GameMode::FourPlayer));
+        assert!(!ranked(GameMode::Practice));
+        assert!(!is_ranked(&MatchRules::default(), &players, true));
+    }
+
+    #[test]
+    fn names_with_spaces_survive_the_file() {
+        let rating = parse_rating("1234.5 3 4 Player One").unwrap();
+
+        assert_eq!(rating.name, "Player One");
+        assert_eq!(rating.rating, 1234.5);
+        assert_eq!((rating.wins, rating.losses), (3, 4));
+        assert!(parse_rating("1200 1 1").is_none());
+        assert!(parse_rating("strong 1 1 Ann").is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use serde::{Serialize, Deserialize};
+use super::{AppState, Ball, BallAssets, Paddle, BALL_RADIUS};
+use super::storage;
+
+pub struct ReplayPlugin;
+
+pub struct ReplaySettings {
+    pub enabled: bool,
+}
+
+// One recorded tick: where every ball and paddle was
+type ReplayFrame = Vec<Recorded>;
+
+#[derive(Clone, Copy)]
+struct Recorded {
+    entity: Entity,
+    translation: Vec3,
+    // Sizes only matter to saved replays, which can't look at the entity any more
+    scale: Vec3,
+    ball: bool,
+}
+
+// A replay kept with the match history, played back without the entities it was recorded from
+#[derive(Default, Serialize, Deserialize)]
+pub struct SavedReplay {
+    pub frames: Vec<Vec<SavedShape>>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SavedShape {
+    // Centre and size in the arena
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+}
+
+// Stands in for a ball that went out earlier in the round, the one it was recorded as is gone
+#[derive(Component)]
+struct ReplayGhost(Entity);
+
+// Everything a replay moves around
+type ReplayQuery<'w, 's> = Query<'w, 's,
+    (Entity, &'static mut Transform, &'static mut Visibility, Option<&'static Ball>, Option<&'static ReplayGhost>),
+    Or<(With<Ball>, With<Paddle>, With<ReplayGhost>)>
+>;
+
+pub struct ReplayBuffer {
+    frames: VecDeque<ReplayFrame>,
+}
+
+impl ReplayBuffer {
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Entities don't outlive the game, so a saved replay is just the shapes of each tick
+    pub fn save(&self, file_name: &str) {
+        let frames = self.frames.iter()
+            .map(|frame| frame.iter().map(|recorded| {
+                // Balls are scaled by their radius
+                let size = if recorded.ball { recorded.scale * 2.0 } else { recorded.scale };
+
+                SavedShape {
+                    position: recorded.translation.truncate().to_array(),
+                    size: size.truncate().to_array(),
+                }
+            }).collect())
+            .collect();
+
+        storage::save(file_name, bincode::serialize(&SavedReplay { frames }).unwrap());
+    }
+}
+
+pub fn load_saved(file_name: &str) -> Option<SavedReplay> {
+    storage::load_bytes(file_name).and_then(|bytes| match bincode::deserialize(&bytes) {
+        Ok(replay) => Some(replay),
+        Err(err) => {
+            warn!("Could not play the replay in {}: {}", file_name, err);
+            None
+        }
+    })
+}
+
+pub struct ReplayPlayback {
+    cursor: f32,
+    // Where everything was before the replay started, restored afterwards
+    resume: ReplayFrame,
+    ui_root: Entity,
+}
+
+impl ReplayPlayback {
+    // Where a ball or paddle goes back to once the replay is over
+    pub fn resume_position(&self, entity: Entity) -> Option<Vec3> {
+        self.resume.iter().find(|recorded| recorded.entity == entity).map(|recorded| recorded.translation)
+    }
+}
+
+// Amount of ticks kept in the buffer, roughly 3 seconds
+const REPLAY_LENGTH: usize = 180;
+// Recorded ticks advanced per frame, < 1.0 gives slow motion
+const REPLAY_SPEED: f32 = 0.4;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(ReplaySettings { enabled: true })
+        .insert_resource(ReplayBuffer { frames: VecDeque::with_capacity(REPLAY_LENGTH) })
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(record_frame)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Replay)
+                .with_system(start_replay)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Replay)
+                .with_system(play_replay)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Replay)
+                .with_system(finish_replay)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Restart)
+                .with_system(clear_replay)
+        );
+    }
+}
+
+fn record_frame(
+    mut buffer: ResMut<ReplayBuffer>,
+    query: Query<(Entity, &Transform, Option<&Ball>), Or<(With<Ball>, With<Paddle>)>>
+) {
+    if buffer.frames.len() == REPLAY_LENGTH {
+        buffer.frames.pop_front();
+    }
+
+    buffer.frames.push_back(record(&query));
+}
+
+fn record(query: &Query<(Entity, &Transform, Option<&Ball>), Or<(With<Ball>, With<Paddle>)>>) -> ReplayFrame {
+    query.iter()
+        .map(|(entity, transform, ball)| Recorded {
+            entity,
+            translation: transform.translation,
+            scale: transform.scale,
+            ball: ball.is_some(),
+        })
+        .collect()
+}
+
+fn start_replay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ball_assets: Res<BallAssets>,
+    buffer: Res<ReplayBuffer>,
+    query: Query<(Entity, &Transform, Option<&Ball>), Or<(With<Ball>, With<Paddle>)>>
+) {
+    let resume = record(&query);
+
+    // In multi-ball every ball but the last one was despawned when it went out
+    let mut gone: Vec<Entity> = Vec::new();
+
+    for recorded in buffer.frames.iter().flatten() {
+        if recorded.ball && query.get(recorded.entity).is_err() && !gone.contains(&recorded.entity) {
+            gone.push(recorded.entity);
+        }
+    }
+
+    for entity in gone {
+        commands.spawn_bundle(MaterialMesh2dBundle {
+            mesh: ball_assets.mesh.clone().into(),
+            transform: Transform {
+                scale: Vec3::new(BALL_RADIUS, BALL_RADIUS, 0.0),
+                ..Default::default()
+            },
+            material: ball_assets.material.clone(),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(ReplayGhost(entity));
+    }
+
+    let ui_root = commands.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            "REPLAY - press Space to skip",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 30.0,
+                color: Color::rgb(0.92, 0.39, 0.20),
+            },
+            Default::default()
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                bottom: Val::Px(20.0),
+                left: Val::Px(20.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).id();
+
+    commands.insert_resource(ReplayPlayback {
+        cursor: 0.0,
+        resume,
+        ui_root,
+    });
+}
+
+fn play_replay(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    buffer: Res<ReplayBuffer>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut query: ReplayQuery
+) {
+    let index = playback.cursor as usize;
+
+    if index >= buffer.frames.len() || keyboard_input.just_pressed(KeyCode::Space) {
+        keyboard_input.reset(KeyCode::Space);
+        // A player joining or leaving a hosted match may have changed the state already
+        let _ = app_state.set(AppState::InGame);
+        return;
+    }
+
+    apply_frame(&buffer.frames[index], &mut query);
+    playback.cursor += REPLAY_SPEED;
+}
+
+fn finish_replay(
+    mut commands: Commands,
+    mut buffer: ResMut<ReplayBuffer>,
+    playback: Res<ReplayPlayback>,
+    mut query: ReplayQuery
+) {
+    apply_frame(&playback.resume, &mut query);
+
+    for (entity, .., ghost) in query.iter() {
+        if ghost.is_some() {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    // Next replay should only contain the next rally
+    buffer.frames.clear();
+
+    commands.entity(playback.ui_root).despawn_recursive();
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+fn clear_replay(mut buffer: ResMut<ReplayBuffer>) {
+    buffer.frames.clear();
+}
+
+// Balls are only shown on the ticks they were recorded on, the ones put in play
+// for the next round are hidden until the replay is over
+fn apply_frame(frame: &ReplayFrame, query: &mut ReplayQuery) {
+    for (entity, mut transform, mut visibility, ball, ghost) in query.iter_mut() {
+        let recorded_as = ghost.map_or(entity, |ghost| ghost.0);
+        let recorded = frame.iter().find(|recorded| recorded.entity == recorded_as);
+
+        if let Some(recorded) = recorded {
+            transform.translation = recorded.translation;
+        }
+
+        if ball.is_some() || ghost.is_some() {
+            visibility.is_visible = recorded.is_some();
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use bevy::{prelude::*, app::AppExit};
+use serde::{Serialize, Deserialize};
+use super::{AppState, Ball, Config, Paddle, Player1, Player2, ScoreBoard, scoreboard_system};
+
+mod session;
+mod shim;
+use session::RollbackSession;
+use shim::ShimSocket;
+use super::sim::PlayerInput;
+
+pub struct RollbackPlugin;
+
+// Peer-to-peer match settings given on the command line, see parse_args
+pub struct RollbackConfig {
+    local_port: u16,
+    peer: SocketAddr,
+    local_player: usize,
+    input_delay: u32,
+    latency: Duration,
+    loss: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+enum RollbackMessage {
+    // Player 2 -> player 1 until the match starts
+    Hello,
+    // Player 1 -> player 2, both simulations start from the same seed
+    Start { seed: u64 },
+    // Sender's current frame, the remote inputs it has and its unacknowledged inputs
+    Inputs { frame: u32, ack: u32, start: u32, inputs: Vec<u8> },
+    Ping(f64),
+    Pong(f64),
+}
+
+struct RollbackLink {
+    socket: ShimSocket,
+    peer: SocketAddr,
+    seed: u64,
+    started: bool,
+    last_hello: f64,
+    last_ping: f64,
+    // Round trip time in seconds
+    ping: f64,
+    accumulator: f64,
+}
+
+#[derive(Component)]
+struct StatsText;
+
+const FRAME_TIME: f64 = 1.0 / 60.0;
+const MAX_FRAMES_PER_UPDATE: u32 = 4;
+const HELLO_INTERVAL: f64 = 0.5;
+const PING_INTERVAL: f64 = 1.0;
+
+const USAGE: &str = "usage: pong --rollback <local port> <peer address> <1|2> \
+    [--input-delay <frames>] [--latency <ms>] [--loss <percent>]";
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_system_set(
+            SystemSet::on_enter(AppState::Rollback)
+                .with_system(start_rollback)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Rollback)
+                .with_system(rollback_receive.label("rollback_receive"))
+                .with_system(rollback_simulate.label("rollback_simulate").after("rollback_receive"))
+                .with_system(rollback_send.after("rollback_simulate"))
+                .with_system(rollback_render.label("rollback_render").after("rollback_simulate"))
+                .with_system(scoreboard_system.after("rollback_render"))
+                .with_system(stats_overlay.after("rollback_simulate"))
+                .with_system(rollback_controls)
+        );
+    }
+}
+
+// Rollback matches are started from the command line, so two instances can
+// easily be run side by side:
+//   pong --rollback 7001 127.0.0.1:7002 1 --latency 50 --loss 5
+//   pong --rollback 7002 127.0.0.1:7001 2 --latency 50 --loss 5
+pub fn parse_args() -> Option<RollbackConfig> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|arg| arg.as_str()) != Some("--rollback") {
+        return None;
+    }
+
+    match parse_rollback_args(&args[1..]) {
+        Ok(config) => Some(config),
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn parse_rollback_args(args: &[String]) -> Result<RollbackConfig, String> {
+    if args.len() < 3 {
+        return Err("missing arguments".to_string());
+    }
+
+    let mut config = RollbackConfig {
+        local_port: parse_value("local port", &args[0])?,
+        peer: parse_value("peer address", &args[1])?,
+        local_player: match args[2].as_str() {
+            "1" => 0,
+            "2" => 1,
+            other => return Err(format!("player must be 1 or 2, not '{}'", other)),
+        },
+        input_delay: 2,
+        latency: Duration::ZERO,
+        loss: 0.0,
+    };
+
+    let mut options = args[3..].iter();
+
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or_else(|| format!("missing value for {}", option))?;
+
+        match option.as_str() {
+            "--input-delay" => config.input_delay = parse_value(option, value)?,
+            "--latency" => config.latency = Duration::from_millis(parse_value(option, value)?),
+            "--loss" => config.loss = (parse_value::<f64>(option, value)? / 100.0).clamp(0.0, 1.0),
+            _ => return Err(format!("unknown option '{}'", option)),
+        }
+    }
+
+    Ok(config)
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid {} '{}'", name, value))
+}
+
+fn start_rollback(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rollback_config: Res<RollbackConfig>
+) {
+    let socket = ShimSocket::bind(rollback_config.local_port, rollback_config.latency, rollback_config.loss)
+        .unwrap_or_else(|err| {
+            eprintln!("Could not bind port {}: {}", rollback_config.local_port, err);
+            std::process::exit(1);
+        });
+
+    commands.insert_resource(RollbackLink {
+        socket,
+        peer: rollback_config.peer,
+        // Only player 1's seed is used, it's sent over in the Start message
+        seed: rand::random(),
+        started: false,
+        last_hello: 0.0,
+        last_ping: 0.0,
+        ping: 0.0,
+        accumulator: 0.0,
+    });
+
+    commands.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 16.0,
+                color: Color::rgb(0.92, 0.39, 0.20),
+            },
+            Default::default()
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(StatsText);
+}
+
+fn rollback_receive(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<Config>,
+    rollback_config: Res<RollbackConfig>,
+    mut link: ResMut<RollbackLink>,
+    mut session: Option<ResMut<RollbackSession>>
+) {
+    let now = time.seconds_since_startup();
+
+    for (bytes, addr) in link.socket.receive_all() {
+        if addr != link.peer {
+            continue;
+        }
+
+        let message = match bincode::deserialize::<RollbackMessage>(&bytes) {
+            Ok(message) => message,
+            Err(_) => continue
+        };
+
+        match message {
+            // Keep answering, our Start may have been lost
+            RollbackMessage::Hello if rollback_config.local_player == 0 => {
+                let seed = link.seed;
+                send(&mut link, &RollbackMessage::Start { seed });
+
+                if !link.started {
+                    link.started = true;
+                    commands.insert_resource(RollbackSession::new(&config, seed, 0, rollback_config.input_delay));
+                }
+            },
+            RollbackMessage::Start { seed } if !link.started => {
+                link.started = true;
+                commands.insert_resource(RollbackSession::new(&config, seed, 1, rollback_config.input_delay));
+            },
+            RollbackMessage::Inputs { frame, ack, start, inputs } => {
+                if let Some(ref mut session) = session {
+                    let inputs: Vec<PlayerInput> = inputs.into_iter().map(PlayerInput::from_byte).collect();
+                    session.add_remote_inputs(frame, ack, start, &inputs);
+                }
+            },
+            RollbackMessage::Ping(sent) => send(&mut link, &RollbackMessage::Pong(sent)),
+            RollbackMessage::Pong(sent) => {
+                // Smooth it out a bit so the overlay is readable
+                let sample = now - sent;
+                link.ping = if link.ping == 0.0 { sample } else { link.ping * 0.8 + sample * 0.2 };
+            },
+            _ => {}
+        }
+    }
+}
+
+fn rollback_simulate(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<Config>,
+    mut link: ResMut<RollbackLink>,
+    session: Option<ResMut<RollbackSession>>
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return
+    };
+
+    // Fix up the past first if the remote inputs we just got weren't what we guessed
+    session.rollback(&config);
+
+    let max_accumulated = FRAME_TIME * MAX_FRAMES_PER_UPDATE as f64;
+    link.accumulator = (link.accumulator + time.delta_seconds_f64()).min(max_accumulated);
+
+    let latency_frames = (link.ping / 2.0 / FRAME_TIME).round() as u32;
+
+    while link.accumulator >= FRAME_TIME {
+        if session.should_wait(latency_frames) {
+            session.stats.stalls += 1;
+            break;
+        }
+
+        link.accumulator -= FRAME_TIME;
+
+        // Either set of controls works, there's only one player on this keyboard
+        session.add_local_input(PlayerInput {
+            up: keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up),
+            down: keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down),
+        });
+        session.advance(&config);
+    }
+}
+
+fn rollback_send(
+    time: Res<Time>,
+    mut link: ResMut<RollbackLink>,
+    session: Option<Res<RollbackSession>>
+) {
+    let now = time.seconds_since_startup();
+
+    match session {
+        Some(session) => {
+            let (start, inputs) = session.unacked_local_inputs();
+
+            send(&mut link, &RollbackMessage::Inputs {
+                frame: session.frame(),
+                ack: session.remote_confirmed(),
+                start,
+                inputs: inputs.into_iter().map(PlayerInput::to_byte).collect(),
+            });
+        },
+        None => {
+            if !link.started && now - link.last_hello >= HELLO_INTERVAL {
+                link.last_hello = now;
+                send(&mut link, &RollbackMessage::Hello);
+            }
+        }
+    }
+
+    if now - link.last_ping >= PING_INTERVAL {
+        link.last_ping = now;
+        send(&mut link, &RollbackMessage::Ping(now));
+    }
+
+    link.socket.flush();
+}
+
+fn rollback_render(
+    session: Option<Res<RollbackSession>>,
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut ball_query: Query<&mut Transform, (With<Ball>, Without<Paddle>)>,
+    mut player1_query: Query<&mut Transform, (With<Paddle>, With<Player1>, Without<Player2>)>,
+    mut player2_query: Query<&mut Transform, (With<Paddle>, With<Player2>, Without<Player1>)>
+) {
+    let session = match session {
+        Some(session) => session,
+        None => return
+    };
+    let state = session.state();
+
+    ball_query.single_mut().translation = state.ball_position;
+    player1_query.single_mut().translation.y = state.player1;
+    player2_query.single_mut().translation.y = state.player2;
+
+    scoreboard.player1 = state.score.0;
+    scoreboard.player2 = state.score.1;
+}
+
+fn stats_overlay(
+    link: Res<RollbackLink>,
+    session: Option<Res<RollbackSession>>,
+    mut text_query: Query<&mut Text, With<StatsText>>
+) {
+    let value = match session {
+        Some(session) => {
+            let latency_frames = (link.ping / 2.0 / FRAME_TIME).round() as u32;
+
+            format!(
+                "Ping: {:.0} ms\nFrame: {} (advantage {})\nInput delay: {} frames\nRollbacks: {} ({} frames, last {})\nStalls: {}",
+                link.ping * 1000.0,
+                session.frame(),
+                session.frame_advantage(latency_frames),
+                session.input_delay(),
+                session.stats.rollbacks,
+                session.stats.rollback_frames,
+                session.stats.last_rollback,
+                session.stats.stalls
+            )
+        },
+        None => format!("Waiting for {}..", link.peer)
+    };
+
+    let mut text = text_query.single_mut();
+
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+fn rollback_controls(keyboard_input: Res<Input<KeyCode>>, mut exit: EventWriter<AppExit>) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        exit.send(AppExit);
+    }
+}
+
+fn send(link: &mut RollbackLink, message: &RollbackMessage) {
+    let peer = link.peer;
+    link.socket.send_to(bincode::serialize(message).unwrap(), peer);
+}
@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, VecDeque};
+use crate::Config;
+use crate::sim::{PlayerInput, SimState};
+
+// How many frames the simulation may run ahead of the last confirmed remote input
+const MAX_PREDICTION: u32 = 8;
+// Upper bound on inputs resent in one packet
+const MAX_INPUTS_PER_PACKET: usize = 32;
+// Running more than this many frames ahead of the remote makes us wait for it
+const MAX_FRAME_ADVANTAGE: i64 = 2;
+
+#[derive(Default)]
+pub struct RollbackStats {
+    pub rollbacks: u32,
+    pub rollback_frames: u32,
+    pub last_rollback: u32,
+    pub stalls: u32,
+}
+
+pub struct RollbackSession {
+    local_player: usize,
+    input_delay: u32,
+    // Next frame to simulate, `state` is the state at the start of it
+    frame: u32,
+    state: SimState,
+    // States at the start of every frame that may still be rolled back to
+    saved_states: VecDeque<(u32, SimState)>,
+    local_inputs: BTreeMap<u32, PlayerInput>,
+    remote_inputs: BTreeMap<u32, PlayerInput>,
+    // Remote inputs guessed for frames simulated before the real ones arrived
+    predictions: BTreeMap<u32, PlayerInput>,
+    // Every remote input before this frame is known
+    remote_confirmed: u32,
+    last_remote_input: PlayerInput,
+    // Oldest mispredicted frame, resimulated on the next rollback
+    rollback_to: Option<u32>,
+    // The remote has every local input before this frame
+    remote_ack: u32,
+    // Latest frame the remote told us it is simulating
+    remote_frame: u32,
+    pub stats: RollbackStats,
+}
+
+impl RollbackSession {
+    pub fn new(config: &Config, seed: u64, local_player: usize, input_delay: u32) -> Self {
+        // Nobody can have pressed anything during the frames covered by the input delay
+        let local_inputs = (0..input_delay).map(|frame| (frame, PlayerInput::default())).collect();
+
+        RollbackSession {
+            local_player,
+            input_delay,
+            frame: 0,
+            state: SimState::new(config, seed),
+            saved_states: VecDeque::new(),
+            local_inputs,
+            remote_inputs: BTreeMap::new(),
+            predictions: BTreeMap::new(),
+            remote_confirmed: 0,
+            last_remote_input: PlayerInput::default(),
+            rollback_to: None,
+            remote_ack: 0,
+            remote_frame: 0,
+            stats: RollbackStats::default(),
+        }
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn state(&self) -> &SimState {
+        &self.state
+    }
+
+    pub fn input_delay(&self) -> u32 {
+        self.input_delay
+    }
+
+    pub fn remote_confirmed(&self) -> u32 {
+        self.remote_confirmed
+    }
+
+    pub fn frame_advantage(&self, latency_frames: u32) -> i64 {
+        self.frame as i64 - (self.remote_frame + latency_frames) as i64
+    }
+
+    // Waiting is needed when prediction would go too far, or when we are
+    // running ahead of the remote and should let it catch up
+    pub fn should_wait(&self, latency_frames: u32) -> bool {
+        self.frame >= self.remote_confirmed + MAX_PREDICTION
+            || self.frame_advantage(latency_frames) > MAX_FRAME_ADVANTAGE
+    }
+
+    // Input read on this frame, it takes effect after the input delay
+    pub fn add_local_input(&mut self, input: PlayerInput) {
+        self.local_inputs.insert(self.frame + self.input_delay, input);
+    }
+
+    pub fn add_remote_inputs(&mut self, remote_frame: u32, ack: u32, start: u32, inputs: &[PlayerInput]) {
+        self.remote_frame = self.remote_frame.max(remote_frame);
+        self.remote_ack = self.remote_ack.max(ack);
+
+        for (i, input) in inputs.iter().enumerate() {
+            let frame = start + i as u32;
+
+            // Inputs are taken strictly in order, older ones are already known
+            if frame < self.remote_confirmed {
+                continue;
+            } else if frame > self.remote_confirmed {
+                break;
+            }
+
+            self.remote_inputs.insert(frame, *input);
+            self.last_remote_input = *input;
+            self.remote_confirmed += 1;
+
+            if let Some(predicted) = self.predictions.remove(&frame) {
+                if predicted != *input {
+                    self.rollback_to = Some(self.rollback_to.map_or(frame, |f| f.min(frame)));
+                }
+            }
+        }
+    }
+
+    // Local inputs the remote doesn't have yet and the frame of the first one
+    pub fn unacked_local_inputs(&self) -> (u32, Vec<PlayerInput>) {
+        let inputs = self.local_inputs
+            .range(self.remote_ack..)
+            .map(|(_, input)| *input)
+            .take(MAX_INPUTS_PER_PACKET)
+            .collect();
+
+        (self.remote_ack, inputs)
+    }
+
+    // Go back to the oldest mispredicted frame and simulate up to the current
+    // frame again with the inputs we now know
+    pub fn rollback(&mut self, config: &Config) {
+        let frame = match self.rollback_to.take() {
+            Some(frame) => frame,
+            None => return
+        };
+
+        let index = self.saved_states.iter()
+            .position(|(saved, _)| *saved == frame)
+            .expect("mispredicted frame is no longer saved");
+
+        self.state = self.saved_states[index].1.clone();
+        self.saved_states.truncate(index);
+
+        let target = self.frame;
+        self.frame = frame;
+
+        while self.frame < target {
+            self.simulate_frame(config);
+        }
+
+        self.stats.rollbacks += 1;
+        self.stats.last_rollback = target - frame;
+        self.stats.rollback_frames += target - frame;
+
+        self.prune();
+    }
+
+    pub fn advance(&mut self, config: &Config) {
+        self.simulate_frame(config);
+        self.prune();
+    }
+
+    fn simulate_frame(&mut self, config: &Config) {
+        self.saved_states.push_back((self.frame, self.state.clone()));
+
+        let local = self.local_inputs.get(&self.frame).copied().unwrap_or_default();
+        let remote = match self.remote_inputs.get(&self.frame) {
+            Some(input) => *input,
+            None => {
+                // Guess the remote player keeps holding whatever they held last
+                self.predictions.insert(self.frame, self.last_remote_input);
+                self.last_remote_input
+            }
+        };
+
+        let mut inputs = [PlayerInput::default(); 2];
+        inputs[self.local_player] = local;
+        inputs[1 - self.local_player] = remote;
+
+        self.state.step(config, inputs);
+        self.frame += 1;
+    }
+
+    // Drop everything older than the oldest frame that can still be rolled back to
+    fn prune(&mut self) {
+        let keep_from = self.remote_confirmed.min(self.frame);
+
+        while self.saved_states.front().map_or(false, |(saved, _)| *saved < keep_from) {
+            self.saved_states.pop_front();
+        }
+
+        self.remote_inputs = self.remote_inputs.split_off(&keep_from);
+        self.local_inputs = self.local_inputs.split_off(&keep_from.min(self.remote_ack));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+
+    fn config() -> Config {
+        Config::new(1280.0, 720.0)
+    }
+
+    // Changes every few frames so predictions are sometimes wrong
+    fn input(frame: u32, player: usize) -> PlayerInput {
+        PlayerInput::from_byte(((frame / 7 + player as u32) % 3) as u8)
+    }
+
+    // The match as it should be, simulated straight through with every input known
+    fn reference(config: &Config, frames: u32, input_delay: u32) -> SimState {
+        let mut state = SimState::new(config, SEED);
+
+        for frame in 0..frames {
+            let inputs = [0, 1].map(|player| match frame.checked_sub(input_delay) {
+                Some(read_on) => input(read_on, player),
+                None => PlayerInput::default()
+            });
+            state.step(config, inputs);
+        }
+
+        state
+    }
+
+    fn assert_same(state: &SimState, expected: &SimState) {
+        assert_eq!(state.ball_position, expected.ball_position);
+        assert_eq!(state.ball_velocity, expected.ball_velocity);
+        assert_eq!((state.player1, state.player2), (expected.player1, expected.player2));
+        assert_eq!(state.score, expected.score);
+    }
+
+    #[test]
+    fn misprediction_rolls_back_to_the_real_inputs() {
+        let config = config();
+        let mut session = RollbackSession::new(&config, SEED, 0, 0);
+
+        // The remote is predicted to hold nothing, but it held up from frame 2 on
+        for frame in 0..5 {
+            session.add_local_input(PlayerInput::default());
+            session.advance(&config);
+            assert_eq!(session.frame(), frame + 1);
+        }
+
+        let up = PlayerInput { up: true, down: false };
+        session.add_remote_inputs(5, 0, 0, &[PlayerInput::default(), PlayerInput::default(), up, up, up]);
+        session.rollback(&config);
+
+        let mut expected = SimState::new(&config, SEED);
+        for frame in 0..5 {
+            let remote = if frame < 2 { PlayerInput::default() } else { up };
+            expected.step(&config, [PlayerInput::default(), remote]);
+        }
+
+        assert_same(session.state(), &expected);
+        assert_eq!(session.frame(), 5);
+        assert_eq!((session.stats.rollbacks, session.stats.last_rollback), (1, 3));
+    }
+
+    #[test]
+    fn right_prediction_needs_no_rollback() {
+        let config = config();
+        let mut session = RollbackSession::new(&config, SEED, 1, 0);
+
+        for _ in 0..4 {
+            session.advance(&config);
+        }
+
+        session.add_remote_inputs(4, 0, 0, &[PlayerInput::default(); 4]);
+        session.rollback(&config);
+
+        assert_eq!(session.stats.rollbacks, 0);
+        assert_eq!(session.remote_confirmed(), 4);
+    }
+
+    #[test]
+    fn remote_inputs_are_taken_in_order() {
+        let config = config();
+        let mut session = RollbackSession::new(&config, SEED, 0, 0);
+
+        // Frame 0 is still missing, so nothing after it can be confirmed yet
+        session.add_remote_inputs(3, 0, 1, &[PlayerInput::default(); 2]);
+        assert_eq!(session.remote_confirmed(), 0);
+
+        session.add_remote_inputs(3, 0, 0, &[PlayerInput::default(); 3]);
+        // Inputs sent again are ignored
+        session.add_remote_inputs(3, 0, 0, &[PlayerInput::default(); 3]);
+        assert_eq!(session.remote_confirmed(), 3);
+    }
+
+    #[test]
+    fn prediction_stops_after_a_while() {
+        let config = config();
+        let mut session = RollbackSession::new(&config, SEED, 0, 0);
+
+        // The remote is far ahead but hasn't sent a single input
+        session.add_remote_inputs(100, 0, 0, &[]);
+
+        for _ in 0..MAX_PREDICTION {
+            assert!(!session.should_wait(0));
+            session.advance(&config);
+        }
+
+        assert!(session.should_wait(0));
+    }
+
+    #[test]
+    fn local_inputs_are_resent_until_acked() {
+        let config = config();
+        let mut session = RollbackSession::new(&config, SEED, 0, 2);
+        let up = PlayerInput { up: true, down: false };
+
+        // The input delay covers the first frames with empty inputs
+        session.add_local_input(up);
+        assert_eq!(session.unacked_local_inputs(), (0, vec![PlayerInput::default(), PlayerInput::default(), up]));
+
+        session.add_remote_inputs(0, 2, 0, &[]);
+        assert_eq!(session.unacked_local_inputs(), (2, vec![up]));
+    }
+
+    #[test]
+    fn peers_end_up_with_the_same_match() {
+        const FRAMES: u32 = 300;
+        const LAG: u32 = 3;
+        const INPUT_DELAY: u32 = 2;
+
+        struct Packet {
+            arrives: u32,
+            to: usize,
+            frame: u32,
+            ack: u32,
+            start: u32,
+            inputs: Vec<PlayerInput>,
+        }
+
+        let config = config();
+        let mut sessions = [0, 1].map(|player| RollbackSession::new(&config, SEED, player, INPUT_DELAY));
+        let mut packets: Vec<Packet> = Vec::new();
+
+        // Long enough for both to finish and hear about each other's last inputs
+        for tick in 0..FRAMES * 4 {
+            for (player, session) in sessions.iter_mut().enumerate() {
+                session.rollback(&config);
+
+                if session.frame() < FRAMES && !session.should_wait(LAG) {
+                    session.add_local_input(input(session.frame(), player));
+                    session.advance(&config);
+                }
+
+                let (start, inputs) = session.unacked_local_inputs();
+                packets.push(Packet {
+                    arrives: tick + LAG,
+                    to: 1 - player,
+                    frame: session.frame(),
+                    ack: session.remote_confirmed(),
+                    start,
+                    inputs,
+                });
+            }
+
+            for packet in packets.iter().filter(|packet| packet.arrives == tick) {
+                sessions[packet.to].add_remote_inputs(packet.frame, packet.ack, packet.start, &packet.inputs);
+            }
+            packets.retain(|packet| packet.arrives > tick);
+        }
+
+        let expected = reference(&config, FRAMES, INPUT_DELAY);
+
+        for session in sessions.iter_mut() {
+            session.rollback(&config);
+
+            assert_eq!(session.frame(), FRAMES);
+            assert!(session.remote_confirmed() >= FRAMES);
+            assert_same(session.state(), &expected);
+        }
+
+        assert!(sessions.iter().any(|session| session.stats.rollbacks > 0));
+    }
+}
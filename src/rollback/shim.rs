@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use rand::Rng;
+
+// UDP socket that can hold back and drop outgoing packets, for trying
+// out the netcode on localhost under bad network conditions
+pub struct ShimSocket {
+    socket: UdpSocket,
+    latency: Duration,
+    loss: f64,
+    outgoing: VecDeque<(Instant, Vec<u8>, SocketAddr)>,
+}
+
+impl ShimSocket {
+    pub fn bind(port: u16, latency: Duration, loss: f64) -> io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
+        socket.set_nonblocking(true)?;
+
+        Ok(ShimSocket {
+            socket,
+            latency,
+            loss,
+            outgoing: VecDeque::new(),
+        })
+    }
+
+    pub fn send_to(&mut self, bytes: Vec<u8>, addr: SocketAddr) {
+        if self.loss > 0.0 && rand::thread_rng().gen_bool(self.loss) {
+            return;
+        }
+
+        self.outgoing.push_back((Instant::now() + self.latency, bytes, addr));
+        self.flush();
+    }
+
+    // Send every held back packet whose time has come
+    pub fn flush(&mut self) {
+        let now = Instant::now();
+
+        while self.outgoing.front().map_or(false, |(due, _, _)| *due <= now) {
+            let (_, bytes, addr) = self.outgoing.pop_front().unwrap();
+
+            if let Err(err) = self.socket.send_to(&bytes, addr) {
+                warn!("Sending to {} failed: {}", addr, err);
+            }
+        }
+    }
+
+    pub fn receive_all(&self) -> Vec<(Vec<u8>, SocketAddr)> {
+        let mut packets = Vec::new();
+        let mut buf = [0; 1024];
+
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, addr)) => packets.push((buf[..len].to_vec(), addr)),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Receiving failed: {}", err);
+                    break;
+                }
+            }
+        }
+
+        packets
+    }
+}
@@ -0,0 +1,338 @@
+use bevy::{prelude::*, ecs::system::SystemParam, window::WindowCloseRequested};
+use serde::{Serialize, Deserialize};
+use super::{add_ball, AppState, Ball, BallAssets, MatchRules, MatchSeed, Paddle, Players, ScoreBoard, Side};
+use super::arena::ArenaClock;
+use super::breakout::{self, Brick};
+use super::doubles::FrontPaddle;
+use super::history::MatchLog;
+use super::match_stats::MatchStats;
+use super::net::HostSession;
+use super::power_ups::{ActiveEffects, PowerUp, PowerUpHud, PowerUpSpawner, SavedPowerUps};
+use super::practice::SurvivalTime;
+use super::replay::ReplayPlayback;
+use super::storage;
+use super::tournament::Tournament;
+
+pub struct SavedMatchPlugin;
+
+// A match paused from the menu, written to the save file when the game is quit from there
+// or the window is closed. The replay of the last point isn't kept.
+#[derive(Serialize, Deserialize)]
+pub struct SavedMatch {
+    rules: MatchRules,
+    players: Players,
+    scoreboard: ScoreBoard,
+    seed: u64,
+    balls: Vec<SavedBall>,
+    paddles: Vec<SavedPaddle>,
+    // Positions and hit points of the bricks still standing in breakout
+    bricks: Vec<([f32; 3], u32)>,
+    power_ups: SavedPowerUps,
+    arena_clock: f32,
+    survival_time: f32,
+    stats: MatchStats,
+    log: MatchLog,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedBall {
+    position: [f32; 3],
+    // Includes the speed effects, which are undone when the saved effects run out
+    velocity: [f32; 2],
+    speed_effects: Vec<PowerUp>,
+    // Index into SavedMatch::paddles
+    last_paddle: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPaddle {
+    side: Side,
+    // Side and front tell the paddles apart, doubles has two on each side
+    front: bool,
+    position: [f32; 3],
+}
+
+// The saved match is put back once the restart has set up its mode
+struct Resuming(SavedMatch);
+
+// Everything a snapshot is taken from
+#[derive(SystemParam)]
+struct MatchState<'w, 's> {
+    rules: Res<'w, MatchRules>,
+    players: Res<'w, Players>,
+    scoreboard: Res<'w, ScoreBoard>,
+    seed: Res<'w, MatchSeed>,
+    arena_clock: Res<'w, ArenaClock>,
+    survival_time: Res<'w, SurvivalTime>,
+    stats: Res<'w, MatchStats>,
+    log: Res<'w, MatchLog>,
+    spawner: Res<'w, PowerUpSpawner>,
+    effects: Res<'w, ActiveEffects>,
+    host_session: Option<Res<'w, HostSession>>,
+    tournament: Option<Res<'w, Tournament>>,
+    // Balls and paddles are somewhere else while a replay is shown
+    replay_playback: Option<Res<'w, ReplayPlayback>>,
+    ball_query: Query<'w, 's, (Entity, &'static Transform, &'static Ball)>,
+    paddle_query: Query<'w, 's, (Entity, &'static Transform, &'static Paddle, Option<&'static FrontPaddle>)>,
+    brick_query: Query<'w, 's, (&'static Transform, &'static Brick)>,
+    pickup_query: Query<'w, 's, (&'static Transform, &'static PowerUp)>,
+}
+
+const SAVE_FILE: &str = "match";
+// Written at the start of the file, bumped whenever SavedMatch or anything saved with it changes
+const SAVE_VERSION: u32 = 1;
+
+impl Plugin for SavedMatchPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_system_set(
+            SystemSet::on_enter(AppState::MainMenu)
+                .with_system(take_snapshot)
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::MainMenu)
+                .with_system(drop_snapshot)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Resume)
+                .with_system(resume_match)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(restore_match.before("move_ball"))
+        )
+        .add_system(save_on_close);
+    }
+}
+
+impl SavedMatch {
+    pub fn save(&self) {
+        storage::save(SAVE_FILE, bincode::serialize(&(SAVE_VERSION, self)).unwrap());
+    }
+}
+
+fn decode_saved_match(bytes: &[u8]) -> Result<SavedMatch, String> {
+    let version: u32 = bincode::deserialize(bytes).map_err(|err| err.to_string())?;
+
+    if version != SAVE_VERSION {
+        return Err(format!("unknown version {}", version));
+    }
+
+    let (_, saved): (u32, SavedMatch) = bincode::deserialize(bytes).map_err(|err| err.to_string())?;
+    Ok(saved)
+}
+
+// There's a match to resume from the start menu
+pub fn exists() -> bool {
+    storage::path(SAVE_FILE).exists()
+}
+
+// Network matches need the other player and tournament matches their bracket, neither comes back with a save
+pub fn can_save(networked: bool, tournament: Option<&Tournament>) -> bool {
+    !networked && !tournament.map_or(false, Tournament::is_playing)
+}
+
+fn take_snapshot(mut commands: Commands, state: MatchState) {
+    if let Some(saved) = snapshot(&state) {
+        commands.insert_resource(saved);
+    }
+}
+
+fn snapshot(state: &MatchState) -> Option<SavedMatch> {
+    if !can_save(state.host_session.is_some(), state.tournament.as_deref()) {
+        return None;
+    }
+
+    let position = |entity: Entity, transform: &Transform| state.replay_playback.as_ref()
+        .and_then(|playback| playback.resume_position(entity))
+        .unwrap_or(transform.translation)
+        .to_array();
+
+    let paddles: Vec<(Entity, SavedPaddle)> = state.paddle_query.iter()
+        .map(|(entity, transform, paddle, front)| (entity, SavedPaddle {
+            side: paddle.side,
+            front: front.is_some(),
+            position: position(entity, transform),
+        }))
+        .collect();
+    let paddle_entities: Vec<Entity> = paddles.iter().map(|(entity, _)| *entity).collect();
+
+    let balls = state.ball_query.iter()
+        .map(|(entity, transform, ball)| SavedBall {
+            position: position(entity, transform),
+            velocity: ball.velocity.to_array(),
+            speed_effects: ball.speed_effects.clone(),
+            last_paddle: ball.last_paddle.and_then(|last| paddle_entities.iter().position(|entity| *entity == last)),
+        })
+        .collect();
+
+    let bricks = state.brick_query.iter()
+        .filter(|(_, brick)| brick.hit_points > 0)
+        .map(|(transform, brick)| (transform.translation.to_array(), brick.hit_points))
+        .collect();
+
+    Some(SavedMatch {
+        rules: state.rules.clone(),
+        players: state.players.clone(),
+        scoreboard: state.scoreboard.clone(),
+        seed: state.seed.0,
+        balls,
+        paddles: paddles.into_iter().map(|(_, paddle)| paddle).collect(),
+        bricks,
+        power_ups: SavedPowerUps::new(&state.spawner, &state.effects, &state.pickup_query, &paddle_entities),
+        arena_clock: state.arena_clock.0,
+        survival_time: state.survival_time.0,
+        stats: state.stats.clone(),
+        log: state.log.clone(),
+    })
+}
+
+// Only quitting from the menu saves the match, carrying on doesn't
+fn drop_snapshot(mut commands: Commands) {
+    commands.remove_resource::<SavedMatch>();
+}
+
+// Closing the window in the middle of a match saves it like quitting from the menu does
+fn save_on_close(
+    mut close_requests: EventReader<WindowCloseRequested>,
+    app_state: Res<State<AppState>>,
+    saved: Option<Res<SavedMatch>>,
+    state: MatchState
+) {
+    if close_requests.iter().count() == 0 {
+        return;
+    }
+
+    match app_state.current() {
+        // The snapshot was taken when the menu opened
+        AppState::MainMenu => {
+            if let Some(saved) = saved {
+                saved.save();
+            }
+        },
+        AppState::InGame | AppState::Replay => {
+            if let Some(saved) = snapshot(&state) {
+                saved.save();
+            }
+        },
+        _ => {}
+    }
+}
+
+// The rules and players go in first so the restart sets up the right mode
+fn resume_match(
+    mut commands: Commands,
+    mut app_state: ResMut<State<AppState>>,
+    mut rules: ResMut<MatchRules>,
+    mut players: ResMut<Players>
+) {
+    let saved: Option<SavedMatch> = storage::load_bytes(SAVE_FILE).and_then(|bytes| match decode_saved_match(&bytes) {
+        // A match is only resumed once, quitting it again saves it again
+        Ok(saved) => {
+            storage::remove(SAVE_FILE);
+            Some(saved)
+        },
+        // Kept aside instead of thrown away, an older or newer build may still read it
+        Err(err) => {
+            warn!("Could not read the saved match in {}: {}", SAVE_FILE, err);
+            storage::back_up(SAVE_FILE);
+            None
+        }
+    });
+
+    match saved {
+        Some(saved) => {
+            *rules = saved.rules.clone();
+            *players = saved.players.clone();
+            commands.insert_resource(Resuming(saved));
+            app_state.set(AppState::Restart).unwrap();
+        },
+        None => app_state.set(AppState::Start).unwrap()
+    }
+}
+
+fn restore_match(
+    mut commands: Commands,
+    resuming: Option<Res<Resuming>>,
+    asset_server: Res<AssetServer>,
+    ball_assets: Res<BallAssets>,
+    power_up_hud: Res<PowerUpHud>,
+    mut spawner: ResMut<PowerUpSpawner>,
+    mut effects: ResMut<ActiveEffects>,
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut seed: ResMut<MatchSeed>,
+    mut arena_clock: ResMut<ArenaClock>,
+    mut survival_time: ResMut<SurvivalTime>,
+    mut stats: ResMut<MatchStats>,
+    mut log: ResMut<MatchLog>,
+    ball_query: Query<Entity, With<Ball>>,
+    mut paddle_query: Query<(Entity, &mut Transform, &Paddle, Option<&FrontPaddle>)>,
+    mut brick_query: Query<(Entity, &Transform, &mut Brick, &mut Sprite), Without<Paddle>>
+) {
+    let saved = match resuming {
+        Some(resuming) => resuming,
+        None => return
+    };
+    let saved = &saved.0;
+
+    *scoreboard = saved.scoreboard.clone();
+    seed.0 = saved.seed;
+    arena_clock.0 = saved.arena_clock;
+    survival_time.0 = saved.survival_time;
+    *stats = saved.stats.clone();
+    *log = saved.log.clone();
+
+    // The restart put every paddle of the mode back at its start
+    let mut paddles = vec![None; saved.paddles.len()];
+
+    for (entity, mut transform, paddle, front) in paddle_query.iter_mut() {
+        let index = saved.paddles.iter().position(|saved| saved.side == paddle.side && saved.front == front.is_some());
+
+        if let Some(index) = index {
+            transform.translation = Vec3::from(saved.paddles[index].position);
+            paddles[index] = Some(entity);
+        }
+    }
+
+    for entity in ball_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for ball in &saved.balls {
+        add_ball(&mut commands, &ball_assets, Vec3::from(ball.position), Ball {
+            velocity: Vec2::from(ball.velocity),
+            last_paddle: ball.last_paddle.and_then(|index| paddles.get(index).copied().flatten()),
+            speed_effects: ball.speed_effects.clone(),
+        });
+    }
+
+    saved.power_ups.restore(&mut commands, &asset_server, &power_up_hud, &mut spawner, &mut effects, &paddles);
+
+    // Broken bricks aren't in the save, the rest are found by where they stand
+    for (entity, transform, mut brick, mut sprite) in brick_query.iter_mut() {
+        let position = transform.translation;
+
+        match saved.bricks.iter().find(|(saved, _)| Vec3::from(*saved).distance(position) < 1.0) {
+            Some((_, hit_points)) => {
+                brick.hit_points = *hit_points;
+                sprite.color = breakout::brick_color(*hit_points);
+            },
+            None => commands.entity(entity).despawn()
+        }
+    }
+
+    commands.remove_resource::<Resuming>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_versions_are_not_read() {
+        let newer = bincode::serialize(&(SAVE_VERSION + 1, 0u8)).unwrap();
+
+        assert!(matches!(decode_saved_match(&newer), Err(err) if err.contains("unknown version")));
+        assert!(decode_saved_match(&[1, 2]).is_err());
+    }
+}
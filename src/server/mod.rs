@@ -0,0 +1,318 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::time::Duration;
+use bevy::{prelude::*, app::ScheduleRunnerSettings, core::FixedTimestep, log::LogPlugin};
+use super::{Config, MatchRules, ScoreBoard, WINDOW_WIDTH, WINDOW_HEIGHT};
+use super::net::{bind_socket, receive_all, send};
+use super::net::protocol::{Message, Snapshot, Announcement, PROTOCOL_VERSION, DEFAULT_PORT, DISCOVERY_GROUP, DISCOVERY_PORT};
+use super::sim::{PlayerInput, SimState};
+
+// Everything the dedicated server knows, there's no window or ECS entities,
+// the match only lives in the simulation
+struct Server {
+    id: u64,
+    port: u16,
+    socket: UdpSocket,
+    players: [Option<Player>; 2],
+    spectators: Vec<Spectator>,
+    sim: SimState,
+    status: MatchStatus,
+    tick: u32,
+    last_announce: f64,
+}
+
+struct Player {
+    addr: SocketAddr,
+    name: String,
+    input: PlayerInput,
+    seq: u32,
+    last_received: f64,
+}
+
+struct Spectator {
+    addr: SocketAddr,
+    last_received: f64,
+}
+
+enum MatchStatus {
+    WaitingForPlayers,
+    Playing,
+    // The next match starts a while after this one is won
+    Finished { at: f64 },
+}
+
+struct ServerConfig {
+    port: u16,
+    points_to_win: i32,
+}
+
+const CONNECTION_TIMEOUT: f64 = 5.0;
+const ANNOUNCE_INTERVAL: f64 = 1.0;
+const TIME_BETWEEN_MATCHES: f64 = 5.0;
+const TICKS_PER_SECOND: f64 = 60.0;
+
+const USAGE: &str = "usage: pong-server [--port <port>] [--points <points to win>]";
+
+// Runs matches between two network players without a window, anyone else
+// joining gets to watch. Clients find it from the LAN lobby like any host.
+pub fn run() {
+    let server_config = match parse_args() {
+        Ok(server_config) => server_config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let socket = bind_socket(SocketAddr::from(([0, 0, 0, 0], server_config.port)))
+        .and_then(|socket| socket.set_multicast_loop_v4(true).map(|_| socket))
+        .unwrap_or_else(|err| {
+            eprintln!("Could not bind port {}: {}", server_config.port, err);
+            std::process::exit(1);
+        });
+
+    let config = Config::new(WINDOW_WIDTH, WINDOW_HEIGHT);
+    let sim = SimState::new(&config, rand::random());
+
+    App::new()
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(1.0 / TICKS_PER_SECOND)))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        .insert_resource(MatchRules { points_to_win: server_config.points_to_win, ..Default::default() })
+        .insert_resource(Server {
+            id: rand::random(),
+            port: server_config.port,
+            socket,
+            players: [None, None],
+            spectators: Vec::new(),
+            sim,
+            status: MatchStatus::WaitingForPlayers,
+            tick: 0,
+            last_announce: -ANNOUNCE_INTERVAL,
+        })
+        .insert_resource(config)
+        .add_startup_system(log_start)
+        .add_system(server_receive.label("server_receive"))
+        .add_system_set(
+            SystemSet::new()
+                .with_run_criteria(FixedTimestep::steps_per_second(TICKS_PER_SECOND))
+                .with_system(server_tick.after("server_receive"))
+        )
+        .add_system(server_announce)
+        .run();
+}
+
+fn parse_args() -> Result<ServerConfig, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut server_config = ServerConfig {
+        port: DEFAULT_PORT,
+        points_to_win: MatchRules::default().points_to_win,
+    };
+
+    let mut options = args.iter();
+
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or_else(|| format!("missing value for {}", option))?;
+
+        match option.as_str() {
+            "--port" => server_config.port = parse_value(option, value)?,
+            "--points" => server_config.points_to_win = parse_value(option, value)?,
+            _ => return Err(format!("unknown option '{}'", option)),
+        }
+    }
+
+    if server_config.points_to_win < 1 {
+        return Err("points to win must be at least 1".to_string());
+    }
+
+    Ok(server_config)
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid {} '{}'", name, value))
+}
+
+fn log_start(server: Res<Server>, rules: Res<MatchRules>) {
+    info!("Server running on port {}, first to {} wins", server.port, rules.points_to_win);
+}
+
+fn server_receive(time: Res<Time>, config: Res<Config>, mut server: ResMut<Server>) {
+    let now = time.seconds_since_startup();
+
+    for (message, addr) in receive_all(&server.socket) {
+        let slot = server.players.iter().position(|player| player.as_ref().is_some_and(|p| p.addr == addr));
+
+        match message {
+            Message::Hello { version, name } if version == PROTOCOL_VERSION => {
+                let slot = slot.or_else(|| {
+                    let free = server.players.iter().position(|player| player.is_none())?;
+                    info!("{} joined from {} as player {}", name, addr, free + 1);
+                    server.players[free] = Some(Player {
+                        addr,
+                        name,
+                        input: PlayerInput::default(),
+                        seq: 0,
+                        last_received: now,
+                    });
+                    Some(free)
+                });
+
+                // Hellos keep coming until our Welcome gets through
+                match slot {
+                    Some(slot) => {
+                        server.players[slot].as_mut().unwrap().last_received = now;
+                        send(&server.socket, addr, &Message::Welcome { spectator: false });
+                    },
+                    None => send(&server.socket, addr, &Message::Full),
+                }
+            },
+            Message::Spectate { version } if version == PROTOCOL_VERSION => {
+                match server.spectators.iter_mut().find(|spectator| spectator.addr == addr) {
+                    Some(spectator) => spectator.last_received = now,
+                    None => {
+                        info!("Spectator joined from {}", addr);
+                        server.spectators.push(Spectator { addr, last_received: now });
+                    }
+                }
+
+                send(&server.socket, addr, &Message::Welcome { spectator: true });
+            },
+            Message::KeepAlive => {
+                if let Some(spectator) = server.spectators.iter_mut().find(|spectator| spectator.addr == addr) {
+                    spectator.last_received = now;
+                }
+            },
+            Message::Input { seq, up, down } => {
+                if let Some(player) = slot.and_then(|slot| server.players[slot].as_mut()) {
+                    player.last_received = now;
+
+                    if seq > player.seq {
+                        player.seq = seq;
+                        player.input = PlayerInput { up, down };
+                    }
+                }
+            },
+            Message::Disconnect => match slot {
+                Some(slot) => player_left(&mut server, slot, "left"),
+                None => server.spectators.retain(|spectator| spectator.addr != addr),
+            },
+            _ => {}
+        }
+    }
+
+    for slot in 0..server.players.len() {
+        if server.players[slot].as_ref().is_some_and(|player| now - player.last_received > CONNECTION_TIMEOUT) {
+            player_left(&mut server, slot, "timed out");
+        }
+    }
+
+    server.spectators.retain(|spectator| now - spectator.last_received <= CONNECTION_TIMEOUT);
+
+    if matches!(server.status, MatchStatus::WaitingForPlayers) && server.players.iter().all(Option::is_some) {
+        start_match(&config, &mut server);
+    }
+}
+
+fn server_tick(
+    time: Res<Time>,
+    config: Res<Config>,
+    rules: Res<MatchRules>,
+    mut server: ResMut<Server>
+) {
+    let now = time.seconds_since_startup();
+
+    match server.status {
+        MatchStatus::Playing => {
+            let inputs = [0, 1].map(|slot| server.players[slot].as_ref().map(|player| player.input).unwrap_or_default());
+            server.sim.step(&config, inputs);
+
+            let (player1, player2) = server.sim.score;
+
+            if let Some(winner) = (ScoreBoard { player1, player2, ..Default::default() }).winner(&rules) {
+                let name = |slot: usize| server.players[slot].as_ref().map_or("?", |player| player.name.as_str());
+                let (winner, loser) = if winner == 1 { (0, 1) } else { (1, 0) };
+
+                info!(
+                    "Match over: {} beat {} {}-{}",
+                    name(winner),
+                    name(loser),
+                    player1.max(player2),
+                    player1.min(player2)
+                );
+                server.status = MatchStatus::Finished { at: now };
+            }
+        },
+        MatchStatus::Finished { at } if now - at >= TIME_BETWEEN_MATCHES => {
+            if server.players.iter().all(Option::is_some) {
+                start_match(&config, &mut server);
+            } else {
+                server.status = MatchStatus::WaitingForPlayers;
+            }
+        },
+        _ => {}
+    }
+
+    send_snapshot(&mut server);
+}
+
+fn server_announce(time: Res<Time>, rules: Res<MatchRules>, mut server: ResMut<Server>) {
+    let now = time.seconds_since_startup();
+
+    if now - server.last_announce < ANNOUNCE_INTERVAL {
+        return;
+    }
+
+    server.last_announce = now;
+
+    let message = Message::Announce(Announcement {
+        version: PROTOCOL_VERSION,
+        id: server.id,
+        port: server.port,
+        players: server.players.iter().flatten().map(|player| player.name.clone()).collect(),
+        points_to_win: rules.points_to_win,
+        spectators: server.spectators.len() as u32,
+    });
+
+    // Looped back to lobbies on this machine too
+    send(&server.socket, SocketAddr::from((DISCOVERY_GROUP, DISCOVERY_PORT)), &message);
+}
+
+fn start_match(config: &Config, server: &mut Server) {
+    let names: Vec<&str> = server.players.iter().flatten().map(|player| player.name.as_str()).collect();
+    info!("Match started: {}", names.join(" vs "));
+
+    server.sim = SimState::new(config, rand::random());
+    server.status = MatchStatus::Playing;
+}
+
+fn player_left(server: &mut Server, slot: usize, reason: &str) {
+    if let Some(player) = server.players[slot].take() {
+        info!("{} {}", player.name, reason);
+
+        if matches!(server.status, MatchStatus::Playing) {
+            let (player1, player2) = server.sim.score;
+            info!("Match abandoned at {}-{}", player1, player2);
+            server.status = MatchStatus::WaitingForPlayers;
+        }
+    }
+}
+
+fn send_snapshot(server: &mut Server) {
+    server.tick += 1;
+
+    let message = Message::Snapshot(Snapshot {
+        tick: server.tick,
+        balls: vec![[server.sim.ball_position.x, server.sim.ball_position.y]],
+        player1: server.sim.player1,
+        player2: server.sim.player2,
+        score: server.sim.score,
+        spectators: server.spectators.len() as u32,
+    });
+
+    let addrs = server.players.iter().flatten().map(|player| player.addr)
+        .chain(server.spectators.iter().map(|spectator| spectator.addr));
+
+    for addr in addrs {
+        send(&server.socket, addr, &message);
+    }
+}
@@ -0,0 +1,661 @@
+use bevy::prelude::*;
+use super::{AppState, Controller, Difficulty, GameMode, Handicap, Keys, MatchRules, Paddle, Players, PlayerSettings, Seat, Side, PADDLE_COLORS};
+use super::doubles::FrontPaddle;
+use super::arena::Levels;
+use super::profiles::{self, Profiles};
+use super::storage;
+use super::ui::{self, BUTTON_NORMAL, BUTTON_HOVER, START_BUTTON_NORMAL, START_BUTTON_HOVER, TEXT_COLOR, text_bundle};
+
+pub struct SetupPlugin;
+
+struct SetupScreen {
+    ui_root: Entity,
+}
+
+impl ui::Screen for SetupScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+// The player whose name is being typed in
+struct EditingName(Option<Seat>);
+
+#[derive(Component)]
+enum SetupButton {
+    Mode,
+    PointsToWin,
+    Arena,
+    Color(Seat),
+    Name(Seat),
+    Profile(Seat),
+    Controller(Seat),
+    Keys(Seat),
+    PaddleHeight(Side),
+    PaddleSpeed(Side),
+    StartingPoints(Side),
+    Start,
+    Back
+}
+
+// Clicking an option button moves on to the next value, back to the first after the last
+const POINTS_TO_WIN: [i32; 6] = [10, 15, 21, 3, 5, 7];
+const CONTROLLERS: [Controller; 4] = [
+    Controller::Human,
+    Controller::Computer(Difficulty::Easy),
+    Controller::Computer(Difficulty::Normal),
+    Controller::Computer(Difficulty::Hard),
+];
+const PADDLE_HEIGHTS: [f32; 5] = [1.0, 1.25, 1.5, 0.5, 0.75];
+const PADDLE_SPEEDS: [f32; 5] = [1.0, 1.25, 1.5, 0.5, 0.75];
+const MAX_STARTING_POINTS: i32 = 5;
+const MAX_NAME_LENGTH: usize = 12;
+
+const NARROW_BUTTON: f32 = 80.0;
+const BUTTON_WIDTH: f32 = 130.0;
+const WIDE_BUTTON: f32 = 170.0;
+
+// The selections of the last match started, loaded again on the next launch
+const SETUP_FILE: &str = "setup.last";
+
+impl Plugin for SetupPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(EditingName(None))
+        .add_startup_system(load_setup)
+        .add_system(paint_paddles)
+        .add_system_set(
+            SystemSet::on_enter(AppState::Setup)
+                .with_system(setup_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Setup)
+                .with_system(setup_buttons.label("setup_buttons"))
+                .with_system(setup_controls.label("setup_controls"))
+                .with_system(refresh_screen.after("setup_buttons").after("setup_controls"))
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Setup)
+                .with_system(ui::cleanup::<SetupScreen>)
+        );
+    }
+}
+
+// Lives, hits and the clock decide these modes instead of points
+fn uses_points(mode: GameMode) -> bool {
+    !matches!(mode, GameMode::FourPlayer | GameMode::Practice | GameMode::TimeAttack)
+}
+
+fn setup_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    levels: Res<Levels>,
+    mut editing: ResMut<EditingName>
+) {
+    editing.0 = None;
+
+    let ui_root = spawn_screen(&mut commands, &asset_server, &rules, &players, &levels, &editing);
+    commands.insert_resource(SetupScreen { ui_root });
+}
+
+// Every change shows up on the buttons, and changing the mode changes which players are listed,
+// so the whole screen is built again whenever something changes
+fn refresh_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rules: Res<MatchRules>,
+    players: Res<Players>,
+    levels: Res<Levels>,
+    editing: Res<EditingName>,
+    mut screen: ResMut<SetupScreen>
+) {
+    if !rules.is_changed() && !players.is_changed() && !editing.is_changed() {
+        return;
+    }
+
+    commands.entity(screen.ui_root).despawn_recursive();
+    screen.ui_root = spawn_screen(&mut commands, &asset_server, &rules, &players, &levels, &editing);
+}
+
+fn spawn_screen(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    rules: &MatchRules,
+    players: &Players,
+    levels: &Levels,
+    editing: &EditingName
+) -> Entity {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("Match setup", 50.0, TEXT_COLOR, &font));
+
+        parent.spawn_bundle(row()).with_children(|parent| {
+            spawn_button(parent, &format!("Mode: {}", rules.mode.name()), SetupButton::Mode, BUTTON_NORMAL, WIDE_BUTTON, &font);
+
+            if uses_points(rules.mode) {
+                spawn_button(parent, &format!("First to {}", rules.points_to_win), SetupButton::PointsToWin, BUTTON_NORMAL, WIDE_BUTTON, &font);
+            }
+
+            let arena = levels.0.get(rules.level).map_or("?", |level| level.name.as_str());
+            spawn_button(parent, &format!("Arena: {}", arena), SetupButton::Arena, BUTTON_NORMAL, WIDE_BUTTON, &font);
+        });
+
+        for seat in rules.mode.seats() {
+            let side = seat.side;
+            let settings = &players.0[seat.index()];
+            let handicap = rules.handicaps[side.index()];
+            let name = if editing.0 == Some(seat) {
+                format!("{}_", settings.name)
+            } else {
+                players.seat_name(rules.mode, seat)
+            };
+
+            parent.spawn_bundle(row()).with_children(|parent| {
+                spawn_button(parent, "Color", SetupButton::Color(seat), PADDLE_COLORS[settings.color], NARROW_BUTTON, &font);
+                spawn_button(parent, &name, SetupButton::Name(seat), BUTTON_NORMAL, WIDE_BUTTON, &font);
+                spawn_button(parent, "Profile", SetupButton::Profile(seat), BUTTON_NORMAL, NARROW_BUTTON, &font);
+                spawn_button(parent, controller_text(settings.controller), SetupButton::Controller(seat), BUTTON_NORMAL, WIDE_BUTTON, &font);
+
+                // The other paddles have keys of their own
+                if !side.is_horizontal() && !seat.front {
+                    spawn_button(parent, &format!("Keys: {}", settings.keys.name()), SetupButton::Keys(seat), BUTTON_NORMAL, BUTTON_WIDTH, &font);
+                }
+
+                // Teammates share their side's handicap, it goes on the back player's row
+                if seat.front {
+                    return;
+                }
+
+                spawn_button(parent, &format!("Height {:.0}%", handicap.paddle_height * 100.0), SetupButton::PaddleHeight(side), BUTTON_NORMAL, BUTTON_WIDTH, &font);
+                spawn_button(parent, &format!("Speed {:.0}%", handicap.paddle_speed * 100.0), SetupButton::PaddleSpeed(side), BUTTON_NORMAL, BUTTON_WIDTH, &font);
+
+                // Practice runs score hits, not points
+                if rules.mode != GameMode::Practice {
+                    spawn_button(parent, &format!("Head start {}", handicap.starting_points), SetupButton::StartingPoints(side), BUTTON_NORMAL, BUTTON_WIDTH, &font);
+                }
+            });
+        }
+
+        parent.spawn_bundle(row()).with_children(|parent| {
+            spawn_button(parent, "Start", SetupButton::Start, START_BUTTON_NORMAL, BUTTON_WIDTH, &font);
+            spawn_button(parent, "Back", SetupButton::Back, BUTTON_NORMAL, BUTTON_WIDTH, &font);
+        });
+
+        parent.spawn_bundle(text_bundle(
+            "Click a name to change it, named players get a profile with their stats. Enter starts the match",
+            20.0,
+            TEXT_COLOR,
+            &font
+        ));
+    }).id()
+}
+
+fn controller_text(controller: Controller) -> &'static str {
+    match controller {
+        Controller::Human => "Human",
+        Controller::Computer(Difficulty::Easy) => "Computer (easy)",
+        Controller::Computer(Difficulty::Normal) => "Computer (normal)",
+        Controller::Computer(Difficulty::Hard) => "Computer (hard)",
+    }
+}
+
+fn next_value<T: Copy + PartialEq>(values: &[T], current: T) -> T {
+    let index = values.iter().position(|value| *value == current).map_or(0, |index| index + 1);
+    values[index % values.len()]
+}
+
+fn setup_buttons(
+    mut app_state: ResMut<State<AppState>>,
+    mut rules: ResMut<MatchRules>,
+    mut players: ResMut<Players>,
+    mut editing: ResMut<EditingName>,
+    mut profiles: ResMut<Profiles>,
+    levels: Res<Levels>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &SetupButton),
+        (Changed<Interaction>, With<Button>)>
+) {
+    for (interaction, mut color, setup_button) in interaction_query.iter_mut() {
+        let (normal, hover) = match setup_button {
+            SetupButton::Start => (START_BUTTON_NORMAL, START_BUTTON_HOVER),
+            // Color buttons show the color itself
+            SetupButton::Color(seat) => {
+                let color = PADDLE_COLORS[players.0[seat.index()].color];
+                (color, color)
+            },
+            _ => (BUTTON_NORMAL, BUTTON_HOVER)
+        };
+
+        match *interaction {
+            Interaction::Hovered => *color = hover.into(),
+            Interaction::None => *color = normal.into(),
+            Interaction::Clicked => {
+                // Clicking anywhere else is done with the name
+                if let Some(seat) = editing.0 {
+                    finish_name(&mut players, &profiles, seat);
+                    editing.0 = None;
+                }
+
+                match *setup_button {
+                    SetupButton::Mode => rules.mode = rules.mode.next(),
+                    SetupButton::PointsToWin => {
+                        rules.points_to_win = next_value(&POINTS_TO_WIN, rules.points_to_win);
+
+                        let max_starting_points = max_starting_points(&rules);
+                        for handicap in rules.handicaps.iter_mut() {
+                            handicap.starting_points = handicap.starting_points.min(max_starting_points);
+                        }
+                    },
+                    SetupButton::Arena => rules.level = (rules.level + 1) % levels.0.len(),
+                    SetupButton::Color(seat) => {
+                        let settings = &mut players.0[seat.index()];
+                        settings.color = (settings.color + 1) % PADDLE_COLORS.len();
+                    },
+                    SetupButton::Name(seat) => editing.0 = Some(seat),
+                    SetupButton::Profile(seat) => next_profile(&mut players, &profiles, seat),
+                    SetupButton::Controller(seat) => {
+                        let settings = &mut players.0[seat.index()];
+                        settings.controller = next_value(&CONTROLLERS, settings.controller);
+                    },
+                    SetupButton::Keys(seat) => {
+                        let settings = &mut players.0[seat.index()];
+                        settings.keys = next_value(&Keys::ALL, settings.keys);
+                    },
+                    SetupButton::PaddleHeight(side) => {
+                        let handicap = &mut rules.handicaps[side.index()];
+                        handicap.paddle_height = next_value(&PADDLE_HEIGHTS, handicap.paddle_height);
+                    },
+                    SetupButton::PaddleSpeed(side) => {
+                        let handicap = &mut rules.handicaps[side.index()];
+                        handicap.paddle_speed = next_value(&PADDLE_SPEEDS, handicap.paddle_speed);
+                    },
+                    SetupButton::StartingPoints(side) => {
+                        let max_starting_points = max_starting_points(&rules);
+                        let handicap = &mut rules.handicaps[side.index()];
+                        handicap.starting_points = (handicap.starting_points + 1) % (max_starting_points + 1);
+                    },
+                    SetupButton::Start => start_match(&mut app_state, &rules, &players, &mut profiles, &levels),
+                    // Esc on the same frame may have left already
+                    SetupButton::Back => {
+                        let _ = app_state.set(AppState::Start);
+                    },
+                }
+            }
+        }
+    }
+}
+
+// Profiles nobody else picked in turn, then back to an unnamed guest
+fn next_profile(players: &mut Players, profiles: &Profiles, seat: Seat) {
+    let taken: Vec<&str> = Seat::ALL.iter()
+        .filter(|other| **other != seat)
+        .map(|other| players.0[other.index()].name.as_str())
+        .collect();
+    let current = profiles.0.iter().position(|profile| profile.name == players.0[seat.index()].name);
+
+    let next = profiles.0.iter()
+        .skip(current.map_or(0, |index| index + 1))
+        .find(|profile| !taken.contains(&profile.name.as_str()))
+        .map(|profile| (profile.name.clone(), profile.color, profile.keys));
+
+    let settings = &mut players.0[seat.index()];
+
+    match next {
+        Some((name, color, keys)) => {
+            settings.name = name;
+            settings.color = color;
+            settings.keys = keys;
+        },
+        None => settings.name.clear(),
+    }
+}
+
+// Typing in the name of an existing profile picks that profile's settings
+fn finish_name(players: &mut Players, profiles: &Profiles, seat: Seat) {
+    let settings = &mut players.0[seat.index()];
+    settings.name = settings.name.trim().to_string();
+
+    if let Some(profile) = profiles.find(&settings.name) {
+        settings.color = profile.color;
+        settings.keys = profile.keys;
+    }
+}
+
+// A head start can't win the match on its own
+fn max_starting_points(rules: &MatchRules) -> i32 {
+    MAX_STARTING_POINTS.min(rules.points_to_win - 1)
+}
+
+fn setup_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut received_characters: EventReader<ReceivedCharacter>,
+    mut app_state: ResMut<State<AppState>>,
+    mut editing: ResMut<EditingName>,
+    mut players: ResMut<Players>,
+    mut profiles: ResMut<Profiles>,
+    rules: Res<MatchRules>,
+    levels: Res<Levels>
+) {
+    let typed: Vec<char> = received_characters.iter()
+        .map(|event| event.char)
+        .filter(|c| c.is_ascii_alphanumeric() || *c == ' ')
+        .collect();
+
+    if let Some(seat) = editing.0 {
+        let backspace = keyboard_input.just_pressed(KeyCode::Back);
+        let done = keyboard_input.just_pressed(KeyCode::Return) || keyboard_input.just_pressed(KeyCode::Escape);
+
+        // Only touch the players when something was typed, any change rebuilds the screen
+        if !typed.is_empty() || backspace || done {
+            let name = &mut players.0[seat.index()].name;
+
+            for c in typed {
+                if name.len() < MAX_NAME_LENGTH {
+                    name.push(c);
+                }
+            }
+
+            if backspace {
+                name.pop();
+            }
+        }
+
+        if done {
+            finish_name(&mut players, &profiles, seat);
+            editing.0 = None;
+            keyboard_input.reset(KeyCode::Return);
+            keyboard_input.reset(KeyCode::Escape);
+        }
+
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        start_match(&mut app_state, &rules, &players, &mut profiles, &levels);
+        keyboard_input.reset(KeyCode::Return);
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        let _ = app_state.set(AppState::Start);
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+fn start_match(
+    app_state: &mut ResMut<State<AppState>>,
+    rules: &MatchRules,
+    players: &Players,
+    profiles: &mut Profiles,
+    levels: &Levels
+) {
+    // A click and a key on the same frame both try to leave the screen, only the first one counts
+    if app_state.set(AppState::Restart).is_err() {
+        return;
+    }
+
+    save_setup(rules, players, levels);
+    profiles::remember_players(profiles, players, rules.mode);
+}
+
+fn save_setup(rules: &MatchRules, players: &Players, levels: &Levels) {
+    storage::save(SETUP_FILE, &setup_contents(rules, players, levels));
+}
+
+// One option per line, a player's name goes last since it can have spaces in it
+fn setup_contents(rules: &MatchRules, players: &Players, levels: &Levels) -> String {
+    let mut contents = format!("mode {}\npoints {}\n", rules.mode.name(), rules.points_to_win);
+
+    if let Some(level) = levels.0.get(rules.level) {
+        contents.push_str(&format!("arena {}\n", level.name));
+    }
+
+    // The front players of doubles come after the others, with the handicap of their side
+    for seat in Seat::ALL {
+        let settings = &players.0[seat.index()];
+        let handicap = &rules.handicaps[seat.side.index()];
+
+        contents.push_str(&format!(
+            "player{} {} {} {} {} {} {} {}\n",
+            seat.index() + 1,
+            controller_key(settings.controller),
+            settings.color,
+            settings.keys.id(),
+            handicap.paddle_height,
+            handicap.paddle_speed,
+            handicap.starting_points,
+            settings.name
+        ));
+    }
+
+    contents
+}
+
+fn load_setup(mut rules: ResMut<MatchRules>, mut players: ResMut<Players>, levels: Res<Levels>) {
+    if let Some(contents) = storage::load(SETUP_FILE) {
+        apply_setup(&contents, &mut rules, &mut players, &levels);
+    }
+}
+
+// Anything that doesn't make sense is left at its default
+fn apply_setup(contents: &str, rules: &mut MatchRules, players: &mut Players, levels: &Levels) {
+    for line in contents.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        match key {
+            "mode" => {
+                if let Some(mode) = GameMode::from_name(value) {
+                    rules.mode = mode;
+                }
+            },
+            "points" => {
+                if let Some(points) = value.parse::<i32>().ok().filter(|points| POINTS_TO_WIN.contains(points)) {
+                    rules.points_to_win = points;
+                }
+            },
+            "arena" => {
+                if let Some(level) = levels.0.iter().position(|level| level.name == value) {
+                    rules.level = level;
+                }
+            },
+            _ => {
+                let seat = key.strip_prefix("player")
+                    .and_then(|number| number.parse::<usize>().ok())
+                    .and_then(|number| Seat::ALL.get(number.wrapping_sub(1)));
+
+                match seat.and_then(|seat| parse_player(value).map(|player| (seat, player))) {
+                    Some((seat, (settings, handicap))) => {
+                        players.0[seat.index()] = settings;
+
+                        // The back player's line has the side's handicap
+                        if !seat.front {
+                            rules.handicaps[seat.side.index()] = handicap;
+                        }
+                    },
+                    None => warn!("Ignoring '{}' in {}", line, SETUP_FILE),
+                }
+            }
+        }
+    }
+
+    let max_starting_points = max_starting_points(rules);
+    for handicap in rules.handicaps.iter_mut() {
+        handicap.starting_points = handicap.starting_points.min(max_starting_points);
+    }
+}
+
+fn parse_player(value: &str) -> Option<(PlayerSettings, Handicap)> {
+    let mut parts = value.splitn(7, ' ');
+
+    let key = parts.next()?;
+    let controller = CONTROLLERS.iter().copied().find(|controller| controller_key(*controller) == key)?;
+    let color = parts.next()?.parse::<usize>().ok().filter(|color| *color < PADDLE_COLORS.len())?;
+    let keys = Keys::from_id(parts.next()?)?;
+    let paddle_height = parts.next()?.parse::<f32>().ok().filter(|height| PADDLE_HEIGHTS.contains(height))?;
+    let paddle_speed = parts.next()?.parse::<f32>().ok().filter(|speed| PADDLE_SPEEDS.contains(speed))?;
+    let starting_points = parts.next()?.parse::<i32>().ok().filter(|points| (0..=MAX_STARTING_POINTS).contains(points))?;
+    let name: String = parts.next().unwrap_or("").chars().take(MAX_NAME_LENGTH).collect();
+
+    Some((
+        PlayerSettings { name, controller, color, keys },
+        Handicap { paddle_height, paddle_speed, starting_points }
+    ))
+}
+
+fn controller_key(controller: Controller) -> &'static str {
+    match controller {
+        Controller::Human => "human",
+        Controller::Computer(Difficulty::Easy) => "easy",
+        Controller::Computer(Difficulty::Normal) => "normal",
+        Controller::Computer(Difficulty::Hard) => "hard",
+    }
+}
+
+// Paddles take their player's color, including ones spawned for a new match
+fn paint_paddles(players: Res<Players>, mut paddle_query: Query<(&Paddle, &mut Sprite, Option<&FrontPaddle>)>) {
+    for (paddle, mut sprite, front) in paddle_query.iter_mut() {
+        let seat = Seat { side: paddle.side, front: front.is_some() };
+        let color = PADDLE_COLORS[players.0[seat.index()].color];
+
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+fn row() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            margin: Rect::all(Val::Px(5.0)),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, setup_button: SetupButton, color: Color, width: f32, font: &Handle<Font>) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(width), Val::Px(40.0)),
+            margin: Rect::all(Val::Px(5.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: color.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle(text, 20.0, TEXT_COLOR, font));
+    })
+    .insert(setup_button);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_levels() -> Levels {
+        Levels(Vec::new())
+    }
+
+    #[test]
+    fn player_line_is_read() {
+        let (settings, handicap) = parse_player("hard 3 arrows 1.25 0.75 2 Player One").unwrap();
+
+        assert_eq!(settings, PlayerSettings {
+            name: "Player One".to_string(),
+            controller: Controller::Computer(Difficulty::Hard),
+            color: 3,
+            keys: Keys::Arrows,
+        });
+        assert_eq!(handicap, Handicap { paddle_height: 1.25, paddle_speed: 0.75, starting_points: 2 });
+    }
+
+    #[test]
+    fn player_without_a_name_is_fine() {
+        let (settings, _) = parse_player("human 0 ws 1 1 0").unwrap();
+        assert_eq!(settings.name, "");
+    }
+
+    #[test]
+    fn long_names_are_cut() {
+        let (settings, _) = parse_player("human 0 ws 1 1 0 Abcdefghijklmnopqrstuvwxyz").unwrap();
+        assert_eq!(settings.name.chars().count(), MAX_NAME_LENGTH);
+    }
+
+    #[test]
+    fn players_out_of_range_are_refused() {
+        for line in [
+            "",
+            "robot 0 ws 1 1 0",
+            "human 99 ws 1 1 0",
+            "human 0 mouse 1 1 0",
+            "human 0 ws 3 1 0",
+            "human 0 ws 1 0.1 0",
+            "human 0 ws 1 1 9",
+            "human 0 ws 1 1",
+        ] {
+            assert!(parse_player(line).is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn saved_setup_loads_the_same() {
+        let mut rules = MatchRules { points_to_win: 21, mode: GameMode::Doubles, ..Default::default() };
+        rules.handicaps[Side::Top.index()] = Handicap { paddle_height: 0.5, paddle_speed: 1.5, starting_points: 5 };
+
+        let mut players = Players::default();
+        players.0[Side::Left.index()].name = "Ann Lee".to_string();
+        players.0[Side::Right.index()].controller = Controller::Computer(Difficulty::Easy);
+        players.0[Side::Bottom.index()].color = 5;
+        players.0[Seat::front(Side::Right).index()].name = "Cy".to_string();
+
+        let mut loaded_rules = MatchRules::default();
+        let mut loaded_players = Players::default();
+        apply_setup(&setup_contents(&rules, &players, &no_levels()), &mut loaded_rules, &mut loaded_players, &no_levels());
+
+        assert_eq!((loaded_rules.mode, loaded_rules.points_to_win), (rules.mode, rules.points_to_win));
+        assert_eq!(loaded_rules.handicaps, rules.handicaps);
+        assert_eq!(loaded_players.0, players.0);
+    }
+
+    #[test]
+    fn setup_keeps_defaults_for_nonsense() {
+        let mut rules = MatchRules::default();
+        let mut players = Players::default();
+        let contents = "mode Squash\npoints 11\narena Nowhere\nplayer7 human 0 ws 1 1 0\nplayer1 human 0 ws\nbogus\n";
+
+        apply_setup(contents, &mut rules, &mut players, &no_levels());
+
+        assert_eq!((rules.mode, rules.points_to_win, rules.level), (GameMode::Classic, 10, 0));
+        assert_eq!(players.0, Players::default().0);
+    }
+
+    #[test]
+    fn head_start_stays_below_the_points_to_win() {
+        let mut rules = MatchRules::default();
+        let mut players = Players::default();
+
+        apply_setup("points 3\nplayer1 human 0 ws 1 1 5 Ann\n", &mut rules, &mut players, &no_levels());
+
+        assert_eq!(rules.points_to_win, 3);
+        assert_eq!(rules.handicaps[Side::Left.index()].starting_points, 2);
+        assert_eq!(players.0[Side::Left.index()].name, "Ann");
+    }
+
+    #[test]
+    fn front_players_keep_their_side_handicap() {
+        let mut rules = MatchRules::default();
+        let mut players = Players::default();
+
+        apply_setup("player2 human 0 ws 1.5 1 0 Bob\nplayer6 easy 4 ws 0.5 1 0 Cy\n", &mut rules, &mut players, &no_levels());
+
+        let front = &players.0[Seat::front(Side::Right).index()];
+        assert_eq!((front.name.as_str(), front.controller, front.color), ("Cy", Controller::Computer(Difficulty::Easy), 4));
+        assert_eq!(rules.handicaps[Side::Right.index()].paddle_height, 1.5);
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use crate::{Config, MoveDirection, PADDLE_SPEED};
+use crate::physics::{self, Goal};
+
+// Held paddle keys of one player for one frame, packed into a byte on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PlayerInput {
+    pub up: bool,
+    pub down: bool,
+}
+
+impl PlayerInput {
+    pub fn to_byte(self) -> u8 {
+        self.up as u8 | (self.down as u8) << 1
+    }
+
+    pub fn from_byte(byte: u8) -> Self {
+        PlayerInput {
+            up: byte & 1 != 0,
+            down: byte & 2 != 0,
+        }
+    }
+}
+
+// Complete state of a match, stepping it with the same inputs always gives
+// the same result so it can be saved, restored and simulated again
+#[derive(Clone)]
+pub struct SimState {
+    pub ball_position: Vec3,
+    pub ball_velocity: Vec2,
+    pub player1: f32,
+    pub player2: f32,
+    pub score: (i32, i32),
+    rng: StdRng,
+}
+
+impl SimState {
+    pub fn new(config: &Config, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        SimState {
+            ball_position: Vec3::ZERO,
+            ball_velocity: physics::ball_start_velocity(&mut rng),
+            player1: config.player1_start_position.y,
+            player2: config.player2_start_position.y,
+            score: (0, 0),
+            rng,
+        }
+    }
+
+    pub fn step(&mut self, config: &Config, inputs: [PlayerInput; 2]) {
+        self.player1 = move_paddle(config, self.player1, inputs[0]);
+        self.player2 = move_paddle(config, self.player2, inputs[1]);
+
+        if let Some(goal) = physics::step_ball(config, &mut self.ball_position, &mut self.ball_velocity) {
+            match goal {
+                Goal::Player1 => self.score.0 += 1,
+                Goal::Player2 => self.score.1 += 1,
+            }
+
+            self.ball_position = Vec3::ZERO;
+            self.ball_velocity = physics::ball_start_velocity(&mut self.rng);
+            return;
+        }
+
+        let paddles = [
+            Vec3::new(config.player1_start_position.x, self.player1, 0.0),
+            Vec3::new(config.player2_start_position.x, self.player2, 0.0),
+        ];
+
+        for paddle in paddles.iter() {
+            physics::bounce_off_paddle(
+                self.ball_position,
+                &mut self.ball_velocity,
+                *paddle,
+                physics::paddle_half_size(config)
+            );
+        }
+    }
+}
+
+fn move_paddle(config: &Config, mut y: f32, input: PlayerInput) -> f32 {
+    if input.up {
+        y = physics::move_paddle(config, y, config.paddle_half_height, MoveDirection::UP, PADDLE_SPEED);
+    }
+
+    if input.down {
+        y = physics::move_paddle(config, y, config.paddle_half_height, MoveDirection::DOWN, PADDLE_SPEED);
+    }
+
+    y
+}
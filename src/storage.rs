@@ -0,0 +1,79 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use bevy::prelude::*;
+
+// Everything the game remembers between runs is kept here, next to the assets
+const SAVE_DIRECTORY: &str = "save";
+
+pub fn path(file_name: &str) -> PathBuf {
+    PathBuf::from(SAVE_DIRECTORY).join(file_name)
+}
+
+// Contents of a saved file, or None if nothing has been saved yet
+pub fn load(file_name: &str) -> Option<String> {
+    load_bytes(file_name).and_then(|bytes| match String::from_utf8(bytes) {
+        Ok(contents) => Some(contents),
+        Err(_) => {
+            warn!("Could not read {}: not a text file", path(file_name).display());
+            None
+        }
+    })
+}
+
+pub fn load_bytes(file_name: &str) -> Option<Vec<u8>> {
+    let path = path(file_name);
+
+    match fs::read(&path) {
+        Ok(contents) => Some(contents),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!("Could not read {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+// Failing to save isn't worth interrupting the game for, it just gets logged
+pub fn save(file_name: &str, contents: impl AsRef<[u8]>) {
+    let path = path(file_name);
+    // File names can point into a subdirectory of the save directory
+    let directory = path.parent().unwrap();
+
+    if let Err(err) = fs::create_dir_all(directory).and_then(|_| fs::write(&path, contents)) {
+        warn!("Could not write {}: {}", path.display(), err);
+    }
+}
+
+// Removing a file that was never saved is fine too
+pub fn remove(file_name: &str) {
+    let path = path(file_name);
+
+    match fs::remove_file(&path) {
+        Ok(()) => {},
+        Err(err) if err.kind() == ErrorKind::NotFound => {},
+        Err(err) => warn!("Could not remove {}: {}", path.display(), err),
+    }
+}
+
+// Moves a file that couldn't be read out of the way, so saving doesn't write over it.
+// Returns false if it's still in place.
+pub fn back_up(file_name: &str) -> bool {
+    let path = path(file_name);
+    // Earlier backups are kept too
+    let backup = (1..)
+        .map(|number| PathBuf::from(format!("{}.{}.bak", path.display(), number)))
+        .find(|backup| !backup.exists())
+        .unwrap();
+
+    match fs::rename(&path, &backup) {
+        Ok(()) => {
+            warn!("Moved {} to {}", path.display(), backup.display());
+            true
+        },
+        Err(err) => {
+            warn!("Could not move {} out of the way: {}", path.display(), err);
+            false
+        }
+    }
+}
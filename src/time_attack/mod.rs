@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use super::{AppState, GameMode, MatchRules, ScoreBoard, Side};
+use super::events::MatchWon;
+
+pub struct TimeAttackPlugin;
+
+#[derive(Component)]
+struct ClockText;
+
+impl Plugin for TimeAttackPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_startup_system(setup_clock_text)
+        .add_system(clock_text)
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                // A goal on the last tick is already handled by move_ball
+                .with_system(run_clock.after("move_ball"))
+        );
+    }
+}
+
+// The clock only runs in game, so pauses and replays don't eat into the match
+fn run_clock(
+    time: Res<Time>,
+    rules: Res<MatchRules>,
+    mut scoreboard: ResMut<ScoreBoard>,
+    mut app_state: ResMut<State<AppState>>,
+    mut match_won: EventWriter<MatchWon>
+) {
+    if rules.mode != GameMode::TimeAttack {
+        return;
+    }
+
+    scoreboard.time_left = (scoreboard.time_left - time.delta_seconds()).max(0.0);
+
+    // Without a leader the match goes on to sudden death. When a point or the menu already changed
+    // the state on this tick, the match ends on the next tick in game instead.
+    if let Some(winner) = scoreboard.winner(&rules) {
+        if app_state.set(AppState::GameOver).is_ok() {
+            match_won.send(MatchWon { winner: Side::ALL[winner as usize - 1] });
+        }
+    }
+}
+
+fn setup_clock_text(mut commands: Commands, asset_server: Res<AssetServer>, windows: Res<Windows>) {
+    let window = windows.get_primary().unwrap();
+
+    commands.spawn_bundle(TextBundle {
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 30.0,
+                color: Color::rgb(1.0, 1.0, 1.0),
+            },
+            Default::default()
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(60.0),
+                left: Val::Px(window.width() / 2.0 - 35.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }).insert(ClockText);
+}
+
+fn clock_text(
+    rules: Res<MatchRules>,
+    scoreboard: Res<ScoreBoard>,
+    mut text_query: Query<&mut Text, With<ClockText>>
+) {
+    let value = if rules.mode != GameMode::TimeAttack {
+        String::new()
+    } else if scoreboard.time_left > 0.0 {
+        // Rounded up, so the clock shows 0:00 only when the time is really up
+        let seconds = scoreboard.time_left.ceil() as u32;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        "Sudden death".to_string()
+    };
+
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,1008 @@
+use bevy::prelude::*;
+use super::{AppState, Controller, GameMode, Handicap, Keys, MatchRules, Players, ScoreBoard, Side, PADDLE_COLORS};
+use super::profiles::{self, Profiles};
+use super::storage;
+use super::ui::{self, BUTTON_NORMAL, BUTTON_HOVER, START_BUTTON_NORMAL, START_BUTTON_HOVER, TEXT_COLOR, text_bundle};
+
+pub struct TournamentPlugin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    SingleElimination,
+    RoundRobin,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::SingleElimination => "Single elimination",
+            Format::RoundRobin => "Round robin",
+        }
+    }
+
+    // Used in the save file
+    fn id(self) -> &'static str {
+        match self {
+            Format::SingleElimination => "single",
+            Format::RoundRobin => "round-robin",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Format> {
+        [Format::SingleElimination, Format::RoundRobin].into_iter().find(|format| format.id() == id)
+    }
+}
+
+// One match of the tournament, entrants by index with the left player first
+#[derive(Clone, Default)]
+struct Pairing {
+    players: [Option<usize>; 2],
+    // Index into players
+    winner: Option<usize>,
+    score: [i32; 2],
+}
+
+impl Pairing {
+    fn new(left: usize, right: usize) -> Self {
+        Pairing { players: [Some(left), Some(right)], ..Default::default() }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.winner.is_none() && self.players.iter().all(Option::is_some)
+    }
+
+    fn winning_entrant(&self) -> Option<usize> {
+        self.winner.and_then(|winner| self.players[winner])
+    }
+}
+
+// A tournament in progress, exists as a resource from the draw until it's finished or abandoned
+pub struct Tournament {
+    format: Format,
+    mode: GameMode,
+    points_to_win: i32,
+    // Profile names in seeding order
+    entrants: Vec<String>,
+    rounds: Vec<Vec<Pairing>>,
+    // Round and index of the pairing being played right now
+    playing: Option<(usize, usize)>,
+}
+
+struct Standing {
+    entrant: usize,
+    played: u32,
+    wins: u32,
+    // Points scored minus points conceded
+    difference: i32,
+}
+
+impl Tournament {
+    fn new(format: Format, mode: GameMode, points_to_win: i32, entrants: Vec<String>) -> Self {
+        let rounds = match format {
+            Format::SingleElimination => single_elimination(entrants.len()),
+            Format::RoundRobin => round_robin(entrants.len()),
+        };
+
+        let mut tournament = Tournament { format, mode, points_to_win, entrants, rounds, playing: None };
+        tournament.advance();
+        tournament
+    }
+
+    // A tournament match is being played, or was just finished
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    fn pairing(&self, round: usize, index: usize) -> Option<&Pairing> {
+        self.rounds.get(round).and_then(|pairings| pairings.get(index))
+    }
+
+    fn next_match(&self) -> Option<(usize, usize)> {
+        self.rounds.iter()
+            .enumerate()
+            .flat_map(|(round, pairings)| pairings.iter().enumerate().map(move |(index, pairing)| (round, index, pairing)))
+            .find(|(_, _, pairing)| pairing.is_ready())
+            .map(|(round, index, _)| (round, index))
+    }
+
+    fn record(&mut self, round: usize, index: usize, winner: usize, score: [i32; 2]) -> bool {
+        match self.rounds.get_mut(round).and_then(|pairings| pairings.get_mut(index)) {
+            Some(pairing) if pairing.is_ready() && winner < 2 => {
+                pairing.winner = Some(winner);
+                pairing.score = score;
+            },
+            _ => return false
+        }
+
+        self.advance();
+        true
+    }
+
+    // Fills the later rounds of a bracket with the winners so far, and gives byes their walkover
+    fn advance(&mut self) {
+        if self.format != Format::SingleElimination {
+            return;
+        }
+
+        for pairing in self.rounds[0].iter_mut() {
+            if pairing.winner.is_none() {
+                pairing.winner = match pairing.players {
+                    [Some(_), None] => Some(0),
+                    [None, Some(_)] => Some(1),
+                    _ => None
+                };
+            }
+        }
+
+        for round in 1..self.rounds.len() {
+            for index in 0..self.rounds[round].len() {
+                let players = [0, 1].map(|slot| self.rounds[round - 1][index * 2 + slot].winning_entrant());
+                self.rounds[round][index].players = players;
+            }
+        }
+    }
+
+    fn champion(&self) -> Option<usize> {
+        if self.next_match().is_some() {
+            return None;
+        }
+
+        match self.format {
+            Format::SingleElimination => self.rounds.last()?.first()?.winning_entrant(),
+            Format::RoundRobin => self.standings().first().map(|standing| standing.entrant),
+        }
+    }
+
+    // Most wins first, then the best point difference
+    fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = (0..self.entrants.len())
+            .map(|entrant| Standing { entrant, played: 0, wins: 0, difference: 0 })
+            .collect();
+
+        for pairing in self.rounds.iter().flatten() {
+            let winner = match pairing.winner {
+                Some(winner) => winner,
+                None => continue
+            };
+
+            for slot in 0..2 {
+                if let Some(entrant) = pairing.players[slot] {
+                    let standing = &mut standings[entrant];
+                    standing.played += 1;
+                    standing.difference += pairing.score[slot] - pairing.score[1 - slot];
+
+                    if slot == winner {
+                        standing.wins += 1;
+                    }
+                }
+            }
+        }
+
+        standings.sort_by(|a, b| b.wins.cmp(&a.wins).then(b.difference.cmp(&a.difference)));
+        standings
+    }
+
+    fn round_name(&self, round: usize) -> String {
+        match (self.format, self.rounds.len() - round) {
+            (Format::SingleElimination, 1) => "Final".to_string(),
+            (Format::SingleElimination, 2) => "Semifinals".to_string(),
+            _ => format!("Round {}", round + 1),
+        }
+    }
+}
+
+// Seeds are placed so the top seeds only meet late, and the byes that fill the
+// bracket up to a power of two go to the top seeds
+fn single_elimination(entrants: usize) -> Vec<Vec<Pairing>> {
+    let size = entrants.next_power_of_two().max(2);
+
+    let mut seeds = vec![0];
+    while seeds.len() < size {
+        let count = seeds.len() * 2;
+        seeds = seeds.iter().flat_map(|seed| [*seed, count - 1 - seed]).collect();
+    }
+
+    let first_round = seeds.chunks(2)
+        .map(|pair| Pairing {
+            players: [pair[0], pair[1]].map(|seed| Some(seed).filter(|seed| *seed < entrants)),
+            ..Default::default()
+        })
+        .collect();
+
+    let mut rounds = vec![first_round];
+    let mut pairings = size / 4;
+
+    while pairings > 0 {
+        rounds.push(vec![Pairing::default(); pairings]);
+        pairings /= 2;
+    }
+
+    rounds
+}
+
+// Everyone plays everyone once, arranged in rounds with the circle method so
+// everyone plays at most once per round
+fn round_robin(entrants: usize) -> Vec<Vec<Pairing>> {
+    let mut seats: Vec<Option<usize>> = (0..entrants).map(Some).collect();
+
+    // Whoever meets the empty seat sits the round out
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+
+    let count = seats.len();
+
+    (1..count).map(|_| {
+        let round = (0..count / 2)
+            .filter_map(|seat| match (seats[seat], seats[count - 1 - seat]) {
+                (Some(left), Some(right)) => Some(Pairing::new(left, right)),
+                _ => None
+            })
+            .collect();
+
+        // The first seat stays put and everyone else moves one over
+        seats[1..].rotate_right(1);
+        round
+    }).collect()
+}
+
+// Entrants and the format picked for the next tournament
+struct TournamentDraft {
+    entrants: Vec<String>,
+    format: Format,
+}
+
+struct TournamentScreen {
+    ui_root: Entity,
+    // Whether the screen was built for a tournament or for the draft
+    shows_tournament: bool,
+}
+
+impl ui::Screen for TournamentScreen {
+    fn ui_root(&self) -> Entity {
+        self.ui_root
+    }
+}
+
+#[derive(Component, Clone)]
+enum TournamentButton {
+    Entrant(String),
+    Format,
+    Start,
+    Play,
+    Finish,
+    Abandon,
+    Back,
+}
+
+const TOURNAMENT_FILE: &str = "tournament";
+const MAX_ENTRANTS: usize = 8;
+
+const PAIRING_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+const LOSER_COLOR: Color = Color::rgb(0.55, 0.55, 0.55);
+const COLUMN_WIDTH: f32 = 160.0;
+
+impl Plugin for TournamentPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(TournamentDraft { entrants: Vec::new(), format: Format::SingleElimination })
+        .add_startup_system(load_tournament)
+        .add_system_set(
+            SystemSet::on_enter(AppState::GameOver)
+                .with_system(record_result)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Start)
+                .with_system(leave_match)
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Tournament)
+                .with_system(leave_match)
+                .with_system(setup_tournament_screen)
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Tournament)
+                .with_system(tournament_buttons.label("tournament_buttons"))
+                .with_system(tournament_controls.label("tournament_controls"))
+                .with_system(refresh_screen.after("tournament_buttons").after("tournament_controls"))
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::Tournament)
+                .with_system(ui::cleanup::<TournamentScreen>)
+        );
+    }
+}
+
+// The settings and entrants go first, then every result in the order they were played,
+// so loading draws the same bracket again and plays the results back into it
+fn load_tournament(mut commands: Commands) {
+    if let Some(tournament) = storage::load(TOURNAMENT_FILE).and_then(|contents| parse_tournament(&contents)) {
+        commands.insert_resource(tournament);
+    }
+}
+
+fn parse_tournament(contents: &str) -> Option<Tournament> {
+    let mut format = None;
+    let mut mode = None;
+    let mut points_to_win = None;
+    let mut entrants = Vec::new();
+    let mut results = Vec::new();
+
+    for line in contents.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        match key {
+            "format" => format = Format::from_id(value),
+            "mode" => mode = GameMode::from_name(value),
+            "points" => points_to_win = value.parse::<i32>().ok(),
+            "entrant" if !value.is_empty() => entrants.push(value.to_string()),
+            "result" => results.push(value),
+            _ => warn!("Ignoring '{}' in {}", line, TOURNAMENT_FILE),
+        }
+    }
+
+    let mut tournament = match (format, mode, points_to_win) {
+        (Some(format), Some(mode), Some(points_to_win)) if entrants.len() >= 2 => {
+            Tournament::new(format, mode, points_to_win, entrants)
+        },
+        _ => {
+            warn!("Ignoring broken tournament in {}", TOURNAMENT_FILE);
+            return None;
+        }
+    };
+
+    for result in results {
+        let values: Vec<usize> = result.split(' ').filter_map(|value| value.parse().ok()).collect();
+
+        let recorded = match values.as_slice() {
+            [round, index, winner, left, right] => tournament.record(*round, *index, *winner, [*left as i32, *right as i32]),
+            _ => false
+        };
+
+        if !recorded {
+            warn!("Ignoring result '{}' in {}", result, TOURNAMENT_FILE);
+        }
+    }
+
+    Some(tournament)
+}
+
+fn save_tournament(tournament: &Tournament) {
+    storage::save(TOURNAMENT_FILE, &tournament_contents(tournament));
+}
+
+fn tournament_contents(tournament: &Tournament) -> String {
+    let mut contents = format!(
+        "format {}\nmode {}\npoints {}\n",
+        tournament.format.id(),
+        tournament.mode.name(),
+        tournament.points_to_win
+    );
+
+    for entrant in tournament.entrants.iter() {
+        contents.push_str(&format!("entrant {}\n", entrant));
+    }
+
+    // Byes aren't played, the draw gives them again
+    for (round, pairings) in tournament.rounds.iter().enumerate() {
+        for (index, pairing) in pairings.iter().enumerate() {
+            if let (Some(winner), [Some(_), Some(_)]) = (pairing.winner, pairing.players) {
+                contents.push_str(&format!(
+                    "result {} {} {} {} {}\n",
+                    round,
+                    index,
+                    winner,
+                    pairing.score[0],
+                    pairing.score[1]
+                ));
+            }
+        }
+    }
+
+    contents
+}
+
+fn record_result(
+    rules: Res<MatchRules>,
+    scoreboard: Res<ScoreBoard>,
+    tournament: Option<ResMut<Tournament>>
+) {
+    let mut tournament = match tournament {
+        Some(tournament) => tournament,
+        None => return
+    };
+
+    let (round, index) = match tournament.playing {
+        Some(playing) => playing,
+        None => return
+    };
+
+    // Player numbers follow the sides, the left player is the first of the pairing
+    if let Some(winner) = scoreboard.winner(&rules) {
+        tournament.record(round, index, winner as usize - 1, [scoreboard.player1, scoreboard.player2]);
+        save_tournament(&tournament);
+    }
+}
+
+// Going back to the menu or the bracket means the tournament match is over, won or not
+fn leave_match(tournament: Option<ResMut<Tournament>>) {
+    if let Some(mut tournament) = tournament {
+        if tournament.playing.is_some() {
+            tournament.playing = None;
+        }
+    }
+}
+
+fn setup_tournament_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    profiles: Res<Profiles>,
+    draft: Res<TournamentDraft>,
+    tournament: Option<Res<Tournament>>
+) {
+    let ui_root = spawn_screen(&mut commands, &asset_server, &profiles, &draft, tournament.as_deref());
+    commands.insert_resource(TournamentScreen { ui_root, shows_tournament: tournament.is_some() });
+}
+
+fn refresh_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    profiles: Res<Profiles>,
+    draft: Res<TournamentDraft>,
+    tournament: Option<Res<Tournament>>,
+    mut screen: ResMut<TournamentScreen>
+) {
+    let changed = draft.is_changed()
+        || tournament.as_ref().is_some_and(|tournament| tournament.is_changed())
+        || screen.shows_tournament != tournament.is_some();
+
+    if !changed {
+        return;
+    }
+
+    commands.entity(screen.ui_root).despawn_recursive();
+    screen.ui_root = spawn_screen(&mut commands, &asset_server, &profiles, &draft, tournament.as_deref());
+    screen.shows_tournament = tournament.is_some();
+}
+
+fn spawn_screen(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    profiles: &Profiles,
+    draft: &TournamentDraft,
+    tournament: Option<&Tournament>
+) -> Entity {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands.spawn_bundle(ui::screen_root())
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("Tournament", 50.0, TEXT_COLOR, &font));
+
+        match tournament {
+            Some(tournament) => spawn_bracket(parent, tournament, profiles, &font),
+            None => spawn_draft(parent, draft, profiles, &font),
+        }
+    }).id()
+}
+
+fn spawn_draft(parent: &mut ChildBuilder, draft: &TournamentDraft, profiles: &Profiles, font: &Handle<Font>) {
+    if profiles.0.len() < 2 {
+        parent.spawn_bundle(text_bundle(
+            "A tournament needs at least two profiles, give players a name in the match setup",
+            20.0,
+            TEXT_COLOR,
+            font
+        ));
+    } else {
+        parent.spawn_bundle(text_bundle(
+            &format!("Pick up to {} players, in seeding order", MAX_ENTRANTS),
+            20.0,
+            TEXT_COLOR,
+            font
+        ));
+    }
+
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(80.0), Val::Auto),
+            flex_wrap: FlexWrap::Wrap,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for profile in profiles.0.iter() {
+            let seed = draft.entrants.iter().position(|entrant| *entrant == profile.name);
+            let (text, color) = match seed {
+                Some(seed) => (format!("{}. {}", seed + 1, profile.name), PADDLE_COLORS[profile.color]),
+                None => (profile.name.clone(), BUTTON_NORMAL),
+            };
+
+            spawn_button(parent, &text, TournamentButton::Entrant(profile.name.clone()), color, font);
+        }
+    });
+
+    spawn_button(parent, &format!("Format: {}", draft.format.name()), TournamentButton::Format, BUTTON_NORMAL, font);
+
+    parent.spawn_bundle(NodeBundle {
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        if draft.entrants.len() >= 2 {
+            spawn_button(parent, "Start", TournamentButton::Start, START_BUTTON_NORMAL, font);
+        }
+
+        spawn_button(parent, "Back", TournamentButton::Back, BUTTON_NORMAL, font);
+    });
+}
+
+fn spawn_bracket(parent: &mut ChildBuilder, tournament: &Tournament, profiles: &Profiles, font: &Handle<Font>) {
+    parent.spawn_bundle(text_bundle(
+        &format!("{}, {}", tournament.format.name(), tournament.mode.name()),
+        20.0,
+        TEXT_COLOR,
+        font
+    ));
+
+    let color = |entrant: usize| profiles.find(&tournament.entrants[entrant])
+        .map_or(TEXT_COLOR, |profile| PADDLE_COLORS[profile.color]);
+
+    // One column per round
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            margin: Rect::all(Val::Px(10.0)),
+            ..Default::default()
+        },
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for (round, pairings) in tournament.rounds.iter().enumerate() {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(COLUMN_WIDTH), Val::Auto),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    justify_content: JustifyContent::SpaceAround,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(text_bundle(&tournament.round_name(round), 16.0, LOSER_COLOR, font));
+
+                for pairing in pairings.iter() {
+                    spawn_pairing(parent, tournament, pairing, &color, font);
+                }
+            });
+        }
+    });
+
+    if tournament.format == Format::RoundRobin {
+        for standing in tournament.standings() {
+            let difference = format!("{:+}", standing.difference);
+            let text = format!(
+                "{}   {} played, {} won, {}",
+                tournament.entrants[standing.entrant],
+                standing.played,
+                standing.wins,
+                difference
+            );
+
+            parent.spawn_bundle(text_bundle(&text, 16.0, color(standing.entrant), font));
+        }
+    }
+
+    let status = match (tournament.next_match(), tournament.champion()) {
+        (Some((round, index)), _) => {
+            let pairing = &tournament.rounds[round][index];
+            let [left, right] = pairing.players.map(|entrant| tournament.entrants[entrant.unwrap()].as_str());
+            format!("Next up: {} vs {}", left, right)
+        },
+        (None, Some(champion)) => format!("{} wins the tournament!", tournament.entrants[champion]),
+        (None, None) => String::new(),
+    };
+
+    parent.spawn_bundle(text_bundle(&status, 30.0, Color::rgb(0.92, 0.39, 0.20), font));
+
+    parent.spawn_bundle(NodeBundle {
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        if tournament.next_match().is_some() {
+            spawn_button(parent, "Play", TournamentButton::Play, START_BUTTON_NORMAL, font);
+            spawn_button(parent, "Abandon", TournamentButton::Abandon, BUTTON_NORMAL, font);
+        } else {
+            spawn_button(parent, "Finish", TournamentButton::Finish, START_BUTTON_NORMAL, font);
+        }
+
+        spawn_button(parent, "Back", TournamentButton::Back, BUTTON_NORMAL, font);
+    });
+}
+
+fn spawn_pairing(
+    parent: &mut ChildBuilder,
+    tournament: &Tournament,
+    pairing: &Pairing,
+    color: &dyn Fn(usize) -> Color,
+    font: &Handle<Font>
+) {
+    let sections = (0..2)
+        .map(|slot| {
+            let (value, color) = match pairing.players[slot] {
+                Some(entrant) => {
+                    let name = &tournament.entrants[entrant];
+                    let value = match pairing.winner {
+                        // Byes have no score to show
+                        Some(_) if pairing.players.iter().all(Option::is_some) => format!("{} {}", name, pairing.score[slot]),
+                        _ => name.clone(),
+                    };
+                    let color = if pairing.winner.is_some_and(|winner| winner != slot) { LOSER_COLOR } else { color(entrant) };
+
+                    (value, color)
+                },
+                None if pairing.winner.is_some() => ("Bye".to_string(), LOSER_COLOR),
+                None => ("?".to_string(), LOSER_COLOR),
+            };
+
+            let value = if slot == 0 { format!("{}\n", value) } else { value };
+
+            TextSection {
+                value,
+                style: TextStyle {
+                    font: font.clone(),
+                    font_size: 16.0,
+                    color,
+                },
+            }
+        })
+        .collect();
+
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Px(COLUMN_WIDTH - 10.0), Val::Px(50.0)),
+            margin: Rect::all(Val::Px(5.0)),
+            padding: Rect::all(Val::Px(5.0)),
+            ..Default::default()
+        },
+        color: PAIRING_COLOR.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle {
+            text: Text { sections, ..Default::default() },
+            ..Default::default()
+        });
+    });
+}
+
+fn tournament_buttons(
+    mut commands: Commands,
+    mut app_state: ResMut<State<AppState>>,
+    mut draft: ResMut<TournamentDraft>,
+    mut rules: ResMut<MatchRules>,
+    mut players: ResMut<Players>,
+    mut profiles: ResMut<Profiles>,
+    mut tournament: Option<ResMut<Tournament>>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &TournamentButton),
+        (Changed<Interaction>, With<Button>)>
+) {
+    for (interaction, mut color, tournament_button) in interaction_query.iter_mut() {
+        let (normal, hover) = match tournament_button {
+            TournamentButton::Start | TournamentButton::Play | TournamentButton::Finish => (START_BUTTON_NORMAL, START_BUTTON_HOVER),
+            // Picked players show their color
+            TournamentButton::Entrant(name) if draft.entrants.contains(name) => {
+                let color = profiles.find(name).map_or(BUTTON_NORMAL, |profile| PADDLE_COLORS[profile.color]);
+                (color, color)
+            },
+            _ => (BUTTON_NORMAL, BUTTON_HOVER)
+        };
+
+        match *interaction {
+            Interaction::Hovered => *color = hover.into(),
+            Interaction::None => *color = normal.into(),
+            Interaction::Clicked => match tournament_button {
+                TournamentButton::Entrant(name) => {
+                    match draft.entrants.iter().position(|entrant| entrant == name) {
+                        Some(seed) => {
+                            draft.entrants.remove(seed);
+                        },
+                        None if draft.entrants.len() < MAX_ENTRANTS => draft.entrants.push(name.clone()),
+                        None => {},
+                    }
+                },
+                TournamentButton::Format => {
+                    draft.format = match draft.format {
+                        Format::SingleElimination => Format::RoundRobin,
+                        Format::RoundRobin => Format::SingleElimination,
+                    };
+                },
+                TournamentButton::Start => {
+                    // Matches are one on one, so the modes for more or fewer players fall back to classic
+                    let mode = if rules.mode.sides().len() == 2 { rules.mode } else { GameMode::Classic };
+                    let new_tournament = Tournament::new(draft.format, mode, rules.points_to_win, draft.entrants.clone());
+
+                    save_tournament(&new_tournament);
+                    commands.insert_resource(new_tournament);
+                },
+                TournamentButton::Play => {
+                    if let Some(tournament) = tournament.as_mut() {
+                        play_next_match(tournament, &mut rules, &mut players, &mut profiles);
+                        app_state.set(AppState::Restart).unwrap();
+                    }
+                },
+                TournamentButton::Finish | TournamentButton::Abandon => {
+                    storage::remove(TOURNAMENT_FILE);
+                    commands.remove_resource::<Tournament>();
+                },
+                TournamentButton::Back => app_state.set(AppState::Start).unwrap(),
+            }
+        }
+    }
+}
+
+// Tournament matches are played on even terms, with the usual keys for the left and right players
+fn play_next_match(tournament: &mut Tournament, rules: &mut MatchRules, players: &mut Players, profiles: &mut Profiles) {
+    let (round, index) = match tournament.next_match() {
+        Some(next) => next,
+        None => return
+    };
+
+    let pairing = match tournament.pairing(round, index) {
+        Some(pairing) => pairing,
+        None => return
+    };
+
+    rules.mode = tournament.mode;
+    rules.points_to_win = tournament.points_to_win;
+    rules.handicaps = [Handicap::default(); 4];
+
+    for (side, entrant, keys) in [(Side::Left, pairing.players[0], Keys::WS), (Side::Right, pairing.players[1], Keys::Arrows)] {
+        let name = &tournament.entrants[entrant.unwrap()];
+        let settings = &mut players.0[side.index()];
+
+        settings.name = name.clone();
+        settings.controller = Controller::Human;
+        settings.keys = keys;
+
+        if let Some(profile) = profiles.find(name) {
+            settings.color = profile.color;
+        }
+    }
+
+    profiles::remember_players(profiles, players, rules.mode);
+    tournament.playing = Some((round, index));
+}
+
+fn tournament_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::Start).unwrap();
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, tournament_button: TournamentButton, color: Color, font: &Handle<Font>) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(COLUMN_WIDTH), Val::Px(40.0)),
+            margin: Rect::all(Val::Px(5.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: color.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle(text, 20.0, TEXT_COLOR, font));
+    })
+    .insert(tournament_button);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [usize; 4] = [2, 3, 5, 8];
+
+    fn entrants(count: usize) -> Vec<String> {
+        (0..count).map(|entrant| format!("Player {}", entrant + 1)).collect()
+    }
+
+    // Plays the next match until there's none left, the left player always wins
+    fn play_out(tournament: &mut Tournament) {
+        while let Some((round, index)) = tournament.next_match() {
+            assert!(tournament.record(round, index, 0, [5, 2]));
+        }
+    }
+
+    #[test]
+    fn single_elimination_places_everyone_once() {
+        for entrants in SIZES {
+            let rounds = single_elimination(entrants);
+            let size = entrants.next_power_of_two();
+
+            assert_eq!(rounds.len(), size.trailing_zeros() as usize, "{} entrants", entrants);
+            assert_eq!(rounds[0].len(), size / 2, "{} entrants", entrants);
+            assert_eq!(rounds.last().unwrap().len(), 1, "{} entrants", entrants);
+
+            let mut placed: Vec<usize> = rounds[0].iter().flat_map(|pairing| pairing.players).flatten().collect();
+            placed.sort_unstable();
+            assert_eq!(placed, (0..entrants).collect::<Vec<usize>>(), "{} entrants", entrants);
+        }
+    }
+
+    #[test]
+    fn single_elimination_gives_byes_to_top_seeds() {
+        for entrants in SIZES {
+            let byes = entrants.next_power_of_two() - entrants;
+
+            let mut seeds_with_byes: Vec<usize> = single_elimination(entrants)[0].iter()
+                .filter_map(|pairing| match pairing.players {
+                    [Some(seed), None] | [None, Some(seed)] => Some(seed),
+                    _ => None
+                })
+                .collect();
+            seeds_with_byes.sort_unstable();
+
+            assert_eq!(seeds_with_byes, (0..byes).collect::<Vec<usize>>(), "{} entrants", entrants);
+        }
+    }
+
+    #[test]
+    fn single_elimination_keeps_top_seeds_apart() {
+        let rounds = single_elimination(8);
+        let half = rounds[0].len() / 2;
+        let in_first_half = |seed| rounds[0][..half].iter().any(|pairing| pairing.players.contains(&Some(seed)));
+
+        assert_ne!(in_first_half(0), in_first_half(1));
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once() {
+        for entrants in SIZES {
+            let rounds = round_robin(entrants);
+            let mut meetings = vec![vec![0; entrants]; entrants];
+
+            assert_eq!(rounds.len(), if entrants % 2 == 0 { entrants - 1 } else { entrants }, "{} entrants", entrants);
+
+            for round in rounds.iter() {
+                let mut playing: Vec<usize> = round.iter().flat_map(|pairing| pairing.players).flatten().collect();
+                let count = playing.len();
+                playing.sort_unstable();
+                playing.dedup();
+                assert_eq!(playing.len(), count, "someone plays twice in a round of {} entrants", entrants);
+
+                for pairing in round.iter() {
+                    let [left, right] = pairing.players.map(Option::unwrap);
+                    meetings[left][right] += 1;
+                    meetings[right][left] += 1;
+                }
+            }
+
+            for (left, row) in meetings.iter().enumerate() {
+                for (right, count) in row.iter().enumerate() {
+                    let expected = if left == right { 0 } else { 1 };
+                    assert_eq!(*count, expected, "{} and {} of {} entrants", left, right, entrants);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn advance_fills_the_bracket_with_winners() {
+        let mut tournament = Tournament::new(Format::SingleElimination, GameMode::Classic, 5, entrants(3));
+
+        // The top seed's bye is a walkover straight into the final
+        assert_eq!(tournament.rounds[0][0].winning_entrant(), Some(0));
+        assert_eq!(tournament.rounds[1][0].players, [Some(0), None]);
+        assert_eq!(tournament.next_match(), Some((0, 1)));
+
+        assert!(tournament.record(0, 1, 1, [3, 5]));
+        assert_eq!(tournament.rounds[1][0].players, [Some(0), Some(2)]);
+        assert_eq!(tournament.champion(), None);
+
+        assert!(tournament.record(1, 0, 1, [4, 5]));
+        assert_eq!(tournament.next_match(), None);
+        assert_eq!(tournament.champion(), Some(2));
+    }
+
+    #[test]
+    fn every_tournament_can_be_played_out() {
+        for format in [Format::SingleElimination, Format::RoundRobin] {
+            for count in SIZES {
+                let mut tournament = Tournament::new(format, GameMode::Classic, 5, entrants(count));
+                play_out(&mut tournament);
+
+                assert!(tournament.champion().is_some(), "{:?} with {} entrants", format, count);
+            }
+        }
+    }
+
+    #[test]
+    fn record_refuses_finished_and_unknown_matches() {
+        let mut tournament = Tournament::new(Format::RoundRobin, GameMode::Classic, 5, entrants(2));
+
+        assert!(!tournament.record(0, 0, 2, [5, 0]));
+        assert!(!tournament.record(3, 0, 0, [5, 0]));
+        assert!(tournament.record(0, 0, 0, [5, 0]));
+        assert!(!tournament.record(0, 0, 1, [0, 5]));
+    }
+
+    #[test]
+    fn standings_sort_by_wins_then_difference() {
+        let mut tournament = Tournament::new(Format::RoundRobin, GameMode::Classic, 5, entrants(3));
+
+        // Everyone wins once, so the point difference decides
+        for (round, pairings) in tournament.rounds.clone().iter().enumerate() {
+            let pairing = &pairings[0];
+            let [left, right] = pairing.players.map(Option::unwrap);
+
+            // Winning entrant and the points of the loser
+            let (winning_entrant, losing_points) = match (left.min(right), left.max(right)) {
+                (0, 1) => (0, 4),
+                (1, 2) => (1, 0),
+                _ => (2, 3),
+            };
+            let winner = if left == winning_entrant { 0 } else { 1 };
+            let score = if winner == 0 { [5, losing_points] } else { [losing_points, 5] };
+
+            assert!(tournament.record(round, 0, winner, score));
+        }
+
+        let standings = tournament.standings();
+        let order: Vec<usize> = standings.iter().map(|standing| standing.entrant).collect();
+
+        // 0 beat 1 by one, 1 beat 2 by five, 2 beat 0 by two
+        assert_eq!(order, vec![1, 0, 2]);
+        assert!(standings.iter().all(|standing| standing.played == 2 && standing.wins == 1));
+        assert_eq!(standings.iter().map(|standing| standing.difference).collect::<Vec<i32>>(), vec![4, -1, -3]);
+        assert_eq!(tournament.champion(), Some(1));
+    }
+
+    #[test]
+    fn saved_tournament_loads_the_same() {
+        for format in [Format::SingleElimination, Format::RoundRobin] {
+            for count in SIZES {
+                let mut tournament = Tournament::new(format, GameMode::MultiBall, 7, entrants(count));
+
+                // Half played, so both results and open matches are in the file
+                for _ in 0..count / 2 {
+                    if let Some((round, index)) = tournament.next_match() {
+                        tournament.record(round, index, 1, [6, 7]);
+                    }
+                }
+
+                let loaded = parse_tournament(&tournament_contents(&tournament)).unwrap();
+
+                assert_eq!(loaded.format, tournament.format);
+                assert_eq!(loaded.mode, tournament.mode);
+                assert_eq!(loaded.points_to_win, tournament.points_to_win);
+                assert_eq!(loaded.entrants, tournament.entrants);
+                assert_eq!(loaded.rounds.len(), tournament.rounds.len());
+
+                for (loaded, saved) in loaded.rounds.iter().flatten().zip(tournament.rounds.iter().flatten()) {
+                    assert_eq!(loaded.players, saved.players);
+                    assert_eq!(loaded.winner, saved.winner);
+                    assert_eq!(loaded.score, saved.score);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn broken_tournament_is_ignored() {
+        assert!(parse_tournament("").is_none());
+        assert!(parse_tournament("format knockout\nmode Classic\npoints 5\nentrant A\nentrant B\n").is_none());
+        assert!(parse_tournament("format single\nmode Classic\npoints 5\nentrant A\n").is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use super::AppState;
+
+// Pieces the full screen menus are built from, so they all look alike
+
+pub const BUTTON_NORMAL: Color = Color::rgb(0.55, 0.55, 0.55);
+pub const BUTTON_HOVER: Color = Color::rgb(0.4, 0.4, 0.4);
+// Buttons that start a match
+pub const START_BUTTON_NORMAL: Color = Color::rgb(0.17, 0.78, 0.19);
+pub const START_BUTTON_HOVER: Color = Color::rgb(0.16, 1.0, 0.18);
+pub const TEXT_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
+// Dims the game behind a screen or a panel
+pub const OVERLAY_COLOR: Color = Color::rgba(0.04, 0.04, 0.04, 0.9);
+
+// The resource a screen stashes its root node in, for cleanup
+pub trait Screen: Send + Sync + 'static {
+    fn ui_root(&self) -> Entity;
+}
+
+// Covers the whole window, the contents go in a centered column from the top down
+pub fn screen_root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: OVERLAY_COLOR.into(),
+        ..Default::default()
+    }
+}
+
+pub fn text_bundle(value: &str, font_size: f32, color: Color, font: &Handle<Font>) -> TextBundle {
+    TextBundle {
+        style: Style {
+            margin: Rect::all(Val::Px(5.0)),
+            ..Default::default()
+        },
+        text: Text::with_section(value, TextStyle {
+            font: font.clone(),
+            font_size,
+            color,
+        }, Default::default()),
+        ..Default::default()
+    }
+}
+
+pub fn cleanup<T: Screen>(mut commands: Commands, screen: Res<T>) {
+    commands.entity(screen.ui_root()).despawn_recursive();
+}
+
+// Leaves a screen for the main menu, see back_controls
+#[derive(Component)]
+pub struct BackButton;
+
+pub fn spawn_back_button(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(150.0), Val::Px(40.0)),
+            margin: Rect::all(Val::Px(20.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: BUTTON_NORMAL.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(text_bundle("Back", 20.0, TEXT_COLOR, font));
+    })
+    .insert(BackButton);
+}
+
+// Esc or a click on the back button goes back to the main menu
+pub fn back_controls(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut interaction_query: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<BackButton>)>
+) {
+    let mut back = keyboard_input.just_pressed(KeyCode::Escape);
+
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Hovered => *color = BUTTON_HOVER.into(),
+            Interaction::None => *color = BUTTON_NORMAL.into(),
+            Interaction::Clicked => back = true,
+        }
+    }
+
+    if back {
+        app_state.set(AppState::Start).unwrap();
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+// A line of a table, every cell is `width` wide so the columns line up
+pub fn spawn_row(parent: &mut ChildBuilder, cells: &[String], width: f32, color: Color, font: &Handle<Font>) {
+    parent.spawn_bundle(NodeBundle {
+        color: Color::NONE.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for cell in cells {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(width), Val::Px(30.0)),
+                    ..Default::default()
+                },
+                color: Color::NONE.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(text_bundle(cell, 20.0, color, font));
+            });
+        }
+    });
+}